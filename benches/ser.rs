@@ -0,0 +1,58 @@
+//! serialization benchmarks, run with `cargo bench`
+
+#[macro_use]
+extern crate criterion;
+#[macro_use]
+extern crate serde_derive;
+extern crate ubyte;
+
+use criterion::{black_box, Criterion};
+use ubyte::options::{Config, U8};
+
+#[derive(Serialize)]
+enum Command {
+    Stop,
+    Move { x: i16, y: i16 },
+    Say(&'static str),
+}
+
+#[derive(Serialize)]
+struct Telemetry {
+    seq: u32,
+    uptime: u64,
+    voltage: f32,
+    flags: [bool; 8],
+    samples: [i16; 16],
+    commands: [Command; 3],
+}
+
+fn telemetry() -> Telemetry {
+    Telemetry {
+        seq: 0xDEAD_BEEF,
+        uptime: 1 << 40,
+        voltage: 3.3,
+        flags: [true, false, true, true, false, false, true, false],
+        samples: [-300, 200, -100, 0, 100, 200, 300, 400, -1, -2, -3, -4, 5, 6, 7, 8],
+        commands: [Command::Stop, Command::Move { x: -5, y: 7 }, Command::Say("hello")],
+    }
+}
+
+fn bench_to_bytes(c: &mut Criterion) {
+    let value = telemetry();
+    let mut buffer = [0u8; 128];
+    let options = Config::new().with_length::<U8, 32>();
+    c.bench_function("to_bytes fixint", |b| {
+        b.iter(|| ubyte::to_bytes_with(options, &mut buffer, black_box(&value)).unwrap())
+    });
+    let options = options.with_varint_encoding();
+    c.bench_function("to_bytes varint", |b| {
+        b.iter(|| ubyte::to_bytes_with(options, &mut buffer, black_box(&value)).unwrap())
+    });
+    let samples = [0x1234_5678u32; 32];
+    c.bench_function("to_bytes [u32; 32]", |b| {
+        b.iter(|| ubyte::to_bytes(&mut buffer, black_box(&samples)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_to_bytes);
+criterion_main!(benches);
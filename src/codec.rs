@@ -0,0 +1,200 @@
+//! tokio codec
+//!
+//! `UbyteCodec` frames messages with a `framing::length_prefixed` header, so
+//! typed requests and responses can be sent over any `AsyncRead +
+//! AsyncWrite`, e.g. `Framed<TcpStream, UbyteCodec<Req, Resp>>`. The codec
+//! encodes `Req` and decodes `Resp`; the other end uses
+//! `UbyteCodec<Resp, Req>`.
+//!
+//! Frames longer than the `max_len` of the codec are rejected on both sides,
+//! with `SerError::LengthLarge` when encoding and `DeError::FrameTooLarge`
+//! when decoding, which ends the stream.
+//!
+//! Like `framing::length_prefixed` the header must be `U8`, `U16` or `U32`; a
+//! codec with a `Disabled` header fails to compile:
+//!
+//! ```compile_fail
+//! # extern crate ubyte;
+//! use ubyte::codec::UbyteCodec;
+//! use ubyte::options::{Disabled, DefaultOptions};
+//!
+//! # fn main() {
+//! let codec = UbyteCodec::<u32, u32, Disabled, _>::new_with(DefaultOptions::new(), 64);
+//! # }
+//! ```
+
+use crate::dev_prefix::*;
+
+use core::marker::PhantomData;
+
+use bytes::BytesMut;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::de;
+use crate::framing::length_prefixed::{self, Header};
+use crate::options::{DefaultOptions, LengthPrefix, Options, U32};
+use crate::ser::Serializer;
+
+/// Encodes `Req` and decodes `Resp` as length-prefixed frames with an `H`
+/// header.
+pub struct UbyteCodec<Req, Resp, H = U32, O = DefaultOptions> {
+    options: O,
+    max_len: usize,
+    messages: PhantomData<fn(Req) -> (Resp, H)>,
+}
+
+impl<Req, Resp> UbyteCodec<Req, Resp> {
+    /// Create a codec with a four byte header and the default options,
+    /// allowing payloads of up to `max_len` bytes.
+    pub fn new(max_len: usize) -> Self {
+        UbyteCodec::new_with(DefaultOptions::new(), max_len)
+    }
+}
+
+impl<Req, Resp, H: LengthPrefix, O: Options> UbyteCodec<Req, Resp, H, O> {
+    /// Create a codec using the encoding `options`, allowing payloads of up
+    /// to `max_len` bytes.
+    ///
+    /// `H` must be `U8`, `U16` or `U32`, `Disabled` fails to compile.
+    pub fn new_with(options: O, max_len: usize) -> Self {
+        let _ = Header::<H>::SIZE;
+        UbyteCodec { options, max_len, messages: PhantomData }
+    }
+
+    /// The longest payload allowed.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+impl<Req, Resp, H, O> Encoder<Req> for UbyteCodec<Req, Resp, H, O>
+    where Req: Serialize,
+          H: LengthPrefix,
+          O: Options
+{
+    type Error = SerError;
+
+    fn encode(&mut self, item: Req, dst: &mut BytesMut) -> SerResult<()> {
+        let start = dst.len();
+        dst.resize(start + Header::<H>::SIZE, 0);
+        let result = match Serializer::with_output(self.options, &mut *dst).write(&item) {
+            Ok(len) if len > self.max_len => Err(SerError::LengthLarge),
+            Ok(len) => {
+                let header = &mut dst[start..start + Header::<H>::SIZE];
+                length_prefixed::write_header::<H, O>(header, len)
+            }
+            Err(err) => Err(err),
+        };
+        // don't leave a partial frame behind
+        if result.is_err() {
+            dst.truncate(start);
+        }
+        result
+    }
+}
+
+impl<Req, Resp, H, O> Decoder for UbyteCodec<Req, Resp, H, O>
+    where Resp: DeserializeOwned,
+          H: LengthPrefix,
+          O: Options
+{
+    type Item = Resp;
+    type Error = DeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> DeResult<Option<Resp>> {
+        let header = length_prefixed::payload_len_with::<H, _>(self.options, src, self.max_len);
+        let len = match header {
+            Ok(len) => len,
+            Err(DeError::BufferSmall) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let frame_len = Header::<H>::SIZE + len;
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+        let frame = src.split_to(frame_len);
+        de::from_bytes_with(self.options, &frame[Header::<H>::SIZE..]).map(Some)
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Request {
+    Ping(u32),
+    Read { channel: u8 },
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Response {
+    Pong(u32),
+    Reading(i16),
+}
+
+#[test]
+fn test_codec_duplex() {
+    use futures::executor::block_on;
+    use futures::{SinkExt, StreamExt};
+    use std::thread;
+    use tokio_util::codec::Framed;
+
+    // a tiny buffer so frames are split across reads and writes
+    let (client, server) = tokio::io::duplex(3);
+    let mut client = Framed::new(client, UbyteCodec::<Request, Response>::new(64));
+    let mut server = Framed::new(server, UbyteCodec::<Response, Request>::new(64));
+
+    let gateway = thread::spawn(move || {
+        while let Some(request) = block_on(server.next()) {
+            let response = match request.unwrap() {
+                Request::Ping(n) => Response::Pong(n + 1),
+                Request::Read { channel } => Response::Reading(-(channel as i16)),
+            };
+            block_on(server.send(response)).unwrap();
+        }
+    });
+
+    block_on(client.send(Request::Ping(41))).unwrap();
+    assert_eq!(block_on(client.next()).unwrap().unwrap(), Response::Pong(42));
+    block_on(client.send(Request::Read { channel: 7 })).unwrap();
+    assert_eq!(block_on(client.next()).unwrap().unwrap(), Response::Reading(-7));
+    drop(client);
+    gateway.join().unwrap();
+}
+
+#[test]
+fn test_codec_frames() {
+    use crate::options::U8;
+
+    let mut codec = UbyteCodec::<Request, Request, U8, _>::new_with(DefaultOptions::new(), 5);
+    let mut bytes = BytesMut::new();
+    codec.encode(Request::Ping(0x01020304), &mut bytes).unwrap();
+    codec.encode(Request::Read { channel: 9 }, &mut bytes).unwrap();
+    assert_eq!(&bytes[..], &[5, 0, 1, 2, 3, 4, 2, 1, 9]);
+
+    // the payload is over `max_len`, nothing is written
+    let mut codec = UbyteCodec::<u32, Request, U8, _>::new_with(DefaultOptions::new(), 3);
+    assert_eq!(codec.encode(7, &mut bytes).unwrap_err(), SerError::LengthLarge);
+    assert_eq!(bytes.len(), 9);
+
+    // frames are only decoded once they are complete
+    let mut codec = UbyteCodec::<Request, Request, U8, _>::new_with(DefaultOptions::new(), 5);
+    let mut src = BytesMut::new();
+    src.extend_from_slice(&bytes[..4]);
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    src.extend_from_slice(&bytes[4..]);
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(Request::Ping(0x01020304)));
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(Request::Read { channel: 9 }));
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+    // a header over `max_len` fails without waiting for the payload
+    src.extend_from_slice(&[6, 0]);
+    assert_eq!(codec.decode(&mut src).unwrap_err(), DeError::FrameTooLarge);
+
+    // the payload must decode to exactly one value
+    let mut src = BytesMut::new();
+    src.extend_from_slice(&[3, 1, 9, 0]);
+    assert_eq!(codec.decode(&mut src).unwrap_err(), DeError::BufferLarge);
+}
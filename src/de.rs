@@ -1,23 +1,202 @@
 //! deserialization module
  
-use dev_prefix::*;
-use core::u8;
+use crate::dev_prefix::*;
 
-use byteorder::{ByteOrder, BigEndian};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use core::str;
+#[cfg(feature = "std")]
+use std::io;
+
+use byteorder::ByteOrder;
+#[cfg(feature = "std")]
+use serde::de::DeserializeOwned;
 use serde::de::{self, Deserialize, DeserializeSeed, Visitor, SeqAccess, EnumAccess,
                 VariantAccess, IntoDeserializer};
 
-pub struct Deserializer<'de> {
-    // Starts with the input data and characters are truncated off
-    // the beginning as data is parsed.
-    input: &'de [u8],
+#[cfg(feature = "path")]
+use crate::error::Path;
+use crate::error::ErrorContext;
+#[cfg(feature = "half")]
+use crate::float;
+use crate::options::{DefaultOptions, IntEncoding, LengthPrefix, Options, TagWidth, UsizeWidth};
+use crate::size;
+use crate::tagged;
+use crate::varint::{self, unzigzag};
+
+/// Where a `Deserializer` reads the encoded bytes from.
+pub trait Input<'de> {
+    /// Take exactly the next `len` bytes, failing with `DeError::BufferSmall`
+    /// if the input ends first.
+    fn take(&mut self, len: usize) -> DeResult<Reference<'de, '_>>;
+}
+
+/// Bytes taken from an `Input`.
+pub enum Reference<'de, 'a> {
+    /// part of the input itself, strings and byte slices can borrow it
+    Borrowed(&'de [u8]),
+    /// a copy that is only valid until the next `take`
+    Copied(&'a [u8]),
+}
+
+impl<'de, 'a> Reference<'de, 'a> {
+    pub fn as_slice(&self) -> &[u8] {
+        match *self {
+            Reference::Borrowed(bytes) => bytes,
+            Reference::Copied(bytes) => bytes,
+        }
+    }
+}
+
+/// Input from a byte slice.
+pub struct SliceInput<'de> {
+    bytes: &'de [u8],
+    // number of bytes taken
+    pos: usize,
+}
+
+impl<'de> SliceInput<'de> {
+    /// Read from the start of `bytes`.
+    pub fn new(bytes: &'de [u8]) -> Self {
+        SliceInput { bytes, pos: 0 }
+    }
+
+    /// The bytes that haven't been taken yet.
+    pub fn remaining(&self) -> &'de [u8] {
+        &self.bytes[self.pos..]
+    }
+}
+
+impl<'de> Input<'de> for SliceInput<'de> {
+    #[inline(always)]
+    fn take(&mut self, len: usize) -> DeResult<Reference<'de, '_>> {
+        let bytes = self.bytes;
+        let end = self.pos + len;
+        match bytes.get(self.pos..end) {
+            Some(taken) => {
+                self.pos = end;
+                Ok(Reference::Borrowed(taken))
+            }
+            None => Err(DeError::BufferSmall),
+        }
+    }
+}
+
+/// Input from an `io::Read`, only reading the bytes that are needed. Every
+/// value is a separate read, so a `BufReader` is recommended for unbuffered
+/// readers.
+#[cfg(feature = "std")]
+pub struct ReaderInput<R> {
+    reader: R,
+    // the bytes of the last `take`
+    scratch: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> ReaderInput<R> {
+    /// Read from `reader`.
+    pub fn new(reader: R) -> Self {
+        ReaderInput { reader, scratch: Vec::new() }
+    }
+
+    /// Take back the reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, R: io::Read> Input<'de> for ReaderInput<R> {
+    #[inline(always)]
+    fn take(&mut self, len: usize) -> DeResult<Reference<'de, '_>> {
+        // `len` is at most `Options::MAX_LEN` (or the size of a number)
+        self.scratch.resize(len, 0);
+        self.reader.read_exact(&mut self.scratch)?;
+        Ok(Reference::Copied(&self.scratch))
+    }
+}
+
+/// Deserializes values from an `Input`, usually a byte slice.
+pub struct Deserializer<R, O = DefaultOptions> {
+    input: R,
+    // number of bytes already taken from `input`
+    offset: usize,
+    // where the value being decoded starts, reported with errors
+    start: usize,
+    #[cfg(feature = "path")]
+    path: Path,
+    options: PhantomData<O>,
 }
 
 
-impl<'de> Deserializer<'de> {
+impl<'de> Deserializer<SliceInput<'de>> {
 	/// Create a deserializer from a byte array
     pub fn from_bytes(input: &'de [u8]) -> Self {
-        Deserializer { input: input }
+        Deserializer::from_bytes_with(DefaultOptions::new(), input)
+    }
+}
+
+impl<'de, O: Options> Deserializer<SliceInput<'de>, O> {
+	/// Create a deserializer from a byte array using the encoding `options`
+    pub fn from_bytes_with(options: O, input: &'de [u8]) -> Self {
+        Deserializer::with_input(options, SliceInput::new(input))
+    }
+
+    /// The part of the input that hasn't been decoded yet.
+    ///
+    /// After an error this starts where decoding stopped.
+    pub fn remaining(&self) -> &'de [u8] {
+        &self.input.bytes[self.offset..]
+    }
+
+    /// Whether all of the input has been decoded.
+    pub fn is_empty(&self) -> bool {
+        self.offset == self.input.bytes.len()
+    }
+}
+
+impl<R, O: Options> Deserializer<R, O> {
+	/// Create a deserializer reading from `input` using the encoding `options`
+    pub fn with_input(_options: O, input: R) -> Self {
+        Deserializer {
+            input,
+            offset: 0,
+            start: 0,
+            #[cfg(feature = "path")]
+            path: Path::new(),
+            options: PhantomData,
+        }
+    }
+
+    /// Take back the input.
+    pub fn into_input(self) -> R {
+        self.input
+    }
+
+    /// The number of bytes of the original input that have been decoded.
+    ///
+    /// After an error this is the offset where decoding stopped.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The struct, field and variant names leading to the value currently
+    /// being decoded.
+    ///
+    /// After an error this is the path to the value that failed.
+    #[cfg(feature = "path")]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Attach where the value being decoded starts (and its path) to `error`.
+    pub fn error_context(&self, error: DeError) -> ErrorContext {
+        ErrorContext {
+            error,
+            offset: self.start,
+            #[cfg(feature = "path")]
+            path: self.path.clone(),
+        }
     }
 }
 
@@ -25,48 +204,301 @@ impl<'de> Deserializer<'de> {
 pub fn from_bytes<'de, T>(bytes: &'de [u8]) -> DeResult<T>
     where T: Deserialize<'de>
 {
-    let mut deserializer = Deserializer::from_bytes(bytes);
-    let t = T::deserialize(&mut deserializer)?;
-    if deserializer.input.is_empty() {
+    from_bytes_with(DefaultOptions::new(), bytes)
+}
+
+/// Same as `from_bytes` but using the encoding `options`.
+pub fn from_bytes_with<'de, O, T>(options: O, bytes: &'de [u8]) -> DeResult<T>
+    where O: Options,
+          T: Deserialize<'de>
+{
+    from_bytes_context_with(options, bytes).map_err(|context| context.error)
+}
+
+/// Same as `from_bytes` but on failure also reports where in `bytes` the
+/// error happened.
+// the context is kept inline rather than boxed so this works without an allocator
+#[allow(clippy::result_large_err)]
+pub fn from_bytes_context<'de, T>(bytes: &'de [u8]) -> Result<T, ErrorContext>
+    where T: Deserialize<'de>
+{
+    from_bytes_context_with(DefaultOptions::new(), bytes)
+}
+
+/// Same as `from_bytes_context` but using the encoding `options`.
+#[allow(clippy::result_large_err)]
+pub fn from_bytes_context_with<'de, O, T>(options: O, bytes: &'de [u8]) -> Result<T, ErrorContext>
+    where O: Options,
+          T: Deserialize<'de>
+{
+    let mut deserializer = Deserializer::from_bytes_with(options, bytes);
+    let t = match T::deserialize(&mut deserializer) {
+        Ok(t) => t,
+        Err(err) => return Err(deserializer.error_context(err)),
+    };
+    if deserializer.is_empty() {
         Ok(t)
     } else {
-        Err(DeError::BufferLarge)
+        // point at the first byte left over
+        deserializer.begin();
+        Err(deserializer.error_context(DeError::BufferLarge))
     }
 }
 
-impl <'de> Deserializer<'de> {
-    /// make sure there is enough buffer left
-    #[inline(always)]
-    fn assert_enough<T>(&self) -> DeResult<()> {
-        if mem::size_of::<T>() <= self.input.len() {
-            Ok(())
-        } else {
-            Err(DeError::BufferSmall)
+/// Deserialize a `T` from `reader`, reading only the bytes it takes up. The
+/// rest of the stream is left for the next value.
+#[cfg(feature = "std")]
+pub fn from_reader<R, T>(reader: R) -> DeResult<T>
+    where R: io::Read,
+          T: DeserializeOwned
+{
+    from_reader_with(DefaultOptions::new(), reader)
+}
+
+/// Same as `from_reader` but using the encoding `options`.
+#[cfg(feature = "std")]
+pub fn from_reader_with<O, R, T>(options: O, reader: R) -> DeResult<T>
+    where O: Options,
+          R: io::Read,
+          T: DeserializeOwned
+{
+    let mut deserializer = Deserializer::with_input(options, ReaderInput::new(reader));
+    T::deserialize(&mut deserializer)
+}
+
+/// Deserialize a `T` from the start of `bytes` and return it along with the
+/// bytes that follow it.
+pub fn take_from_bytes<'de, T>(bytes: &'de [u8]) -> DeResult<(T, &'de [u8])>
+    where T: Deserialize<'de>
+{
+    take_from_bytes_with(DefaultOptions::new(), bytes)
+}
+
+/// Same as `take_from_bytes` but using the encoding `options`.
+pub fn take_from_bytes_with<'de, O, T>(options: O, bytes: &'de [u8]) -> DeResult<(T, &'de [u8])>
+    where O: Options,
+          T: Deserialize<'de>
+{
+    let mut deserializer = Deserializer::from_bytes_with(options, bytes);
+    let t = T::deserialize(&mut deserializer)?;
+    Ok((t, deserializer.remaining()))
+}
+
+/// Iterate over the `T`s stored back to back in `bytes`.
+///
+/// ```
+/// # extern crate ubyte;
+/// # fn main() {
+/// let values: Vec<_> = ubyte::iter_from_bytes::<u16>(&[0, 1, 0, 2]).collect();
+/// assert_eq!(values, vec![Ok(1), Ok(2)]);
+/// # }
+/// ```
+pub fn iter_from_bytes<'de, T>(bytes: &'de [u8]) -> Iter<'de, T>
+    where T: Deserialize<'de>
+{
+    iter_from_bytes_with(DefaultOptions::new(), bytes)
+}
+
+/// Same as `iter_from_bytes` but using the encoding `options`.
+pub fn iter_from_bytes_with<'de, O, T>(options: O, bytes: &'de [u8]) -> Iter<'de, T, O>
+    where O: Options,
+          T: Deserialize<'de>
+{
+    Iter {
+        deserializer: Deserializer::from_bytes_with(options, bytes),
+        failed: false,
+        value: PhantomData,
+    }
+}
+
+/// Iterator over back to back values, see `iter_from_bytes`.
+///
+/// Ends once the input is exhausted. If a value fails to decode the error is
+/// returned and the iterator ends.
+pub struct Iter<'de, T, O = DefaultOptions> {
+    deserializer: Deserializer<SliceInput<'de>, O>,
+    failed: bool,
+    value: PhantomData<T>,
+}
+
+impl<'de, T, O: Options> Iter<'de, T, O> {
+    /// The part of the input that hasn't been decoded yet.
+    ///
+    /// After an error this starts where the failing value stopped decoding.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.deserializer.remaining()
+    }
+
+    /// The number of bytes of the input that have been decoded.
+    pub fn offset(&self) -> usize {
+        self.deserializer.offset()
+    }
+}
+
+impl<'de, T: Deserialize<'de>, O: Options> Iterator for Iter<'de, T, O> {
+    type Item = DeResult<T>;
+
+    fn next(&mut self) -> Option<DeResult<T>> {
+        if self.failed || self.deserializer.is_empty() {
+            return None;
         }
+        let result = T::deserialize(&mut self.deserializer);
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+impl<'de, R: Input<'de>, O: Options> Deserializer<R, O> {
+    /// Start decoding a value at the current offset.
+    #[inline(always)]
+    fn begin(&mut self) {
+        self.start = self.offset;
+    }
+
+    /// take the next `len` bytes from the input
+    #[inline(always)]
+    fn take(&mut self, len: usize) -> DeResult<Reference<'de, '_>> {
+        let bytes = self.input.take(len)?;
+        self.offset += len;
+        Ok(bytes)
     }
 
     #[inline(always)]
     fn consume_u8(&mut self) -> DeResult<u8>{
-        self.assert_enough::<u8>()?;
-        let v = self.input[0];
-        self.consume::<u8>();
-        Ok(v)
+        Ok(self.take(1)?.as_slice()[0])
     }
 
     #[inline(always)]
     fn consume_bool(&mut self) -> DeResult<bool>{
-        Ok(match self.consume_u8()? {
-            0 => false,
-            1 => true,
-            _ => return Err(DeError::ExpectedBoolean),
-        })
+        match self.consume_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DeError::ExpectedBoolean),
+        }
+    }
+
+    #[inline(always)]
+    fn consume_varint(&mut self) -> DeResult<u64> {
+        let mut decoder = varint::Decoder::new();
+        loop {
+            let byte = self.consume_u8()?;
+            match decoder.push(byte) {
+                Ok(Some(v)) => return Ok(v),
+                Ok(None) => {}
+                Err(()) => return Err(DeError::InvalidVarint),
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn consume_usize(&mut self) -> DeResult<u64> {
+        self.begin();
+        if O::Ints::VARINT {
+            let v = self.consume_varint()?;
+            if !O::Usize::fits_unsigned(v) {
+                return Err(DeError::InvalidVarint);
+            }
+            return Ok(v);
+        }
+        let width = O::Usize::MAX_SIZE;
+        Ok(O::Endian::read_uint(self.take(width)?.as_slice(), width))
+    }
+
+    #[inline(always)]
+    fn consume_isize(&mut self) -> DeResult<i64> {
+        self.begin();
+        if O::Ints::VARINT {
+            let v = unzigzag(self.consume_varint()?);
+            if !O::Usize::fits_signed(v) {
+                return Err(DeError::InvalidVarint);
+            }
+            return Ok(v);
+        }
+        let width = O::Usize::MAX_SIZE;
+        Ok(O::Endian::read_int(self.take(width)?.as_slice(), width))
+    }
+
+    /// read a UTF-8 encoded char
+    #[inline(always)]
+    fn consume_char(&mut self) -> DeResult<char> {
+        let first = self.consume_u8()?;
+        let len = match first {
+            b if b < 0x80 => return Ok(b as char),
+            b if b & 0xE0 == 0xC0 => 2,
+            b if b & 0xF0 == 0xE0 => 3,
+            b if b & 0xF8 == 0xF0 => 4,
+            _ => return Err(DeError::InvalidChar),
+        };
+        let mut buffer = [first, 0, 0, 0];
+        buffer[1..len].copy_from_slice(self.take(len - 1)?.as_slice());
+        match str::from_utf8(&buffer[..len]) {
+            Ok(s) => s.chars().next().ok_or(DeError::InvalidChar),
+            Err(_) => Err(DeError::InvalidChar),
+        }
+    }
+
+    /// read a length prefix
+    #[inline(always)]
+    fn consume_len(&mut self, kind: Unsupported) -> DeResult<usize> {
+        self.begin();
+        if !O::Length::ENABLED {
+            return Err(DeError::Unsupported(kind));
+        }
+        let len = O::Length::deserialize(&mut *self)?;
+        if len > O::MAX_LEN {
+            return Err(DeError::LengthLarge);
+        }
+        Ok(len)
+    }
+
+    /// read a length prefixed string or byte slice
+    #[inline(always)]
+    fn consume_bytes(&mut self, kind: Unsupported) -> DeResult<Reference<'de, '_>> {
+        let len = self.consume_len(kind)?;
+        self.take(len)
+    }
+
+    #[cfg(feature = "path")]
+    #[inline(always)]
+    fn push(&mut self, segment: &'static str) {
+        self.path.push(segment);
+    }
+
+    #[cfg(not(feature = "path"))]
+    #[inline(always)]
+    fn push(&mut self, _segment: &'static str) {}
+
+    #[cfg(feature = "path")]
+    #[inline(always)]
+    fn pop(&mut self) {
+        self.path.pop();
+    }
+
+    #[cfg(not(feature = "path"))]
+    #[inline(always)]
+    fn pop(&mut self) {}
+
+    #[cfg(feature = "path")]
+    #[inline(always)]
+    fn path_depth(&self) -> usize {
+        self.path.depth()
+    }
+
+    #[cfg(not(feature = "path"))]
+    #[inline(always)]
+    fn path_depth(&self) -> usize {
+        0
     }
 
+    #[cfg(feature = "path")]
     #[inline(always)]
-    fn consume<T>(&mut self) {
-        let num = mem::size_of::<T>();
-        self.input = &self.input[num..];
+    fn truncate_path(&mut self, depth: usize) {
+        self.path.truncate(depth);
     }
+
+    #[cfg(not(feature = "path"))]
+    #[inline(always)]
+    fn truncate_path(&mut self, _depth: usize) {}
 }
 
 macro_rules! impl_value {
@@ -75,32 +507,53 @@ macro_rules! impl_value {
         fn $de_method<V>(self, visitor: V) -> DeResult<V::Value>
             where V: Visitor<'de>,
         {
-            self.assert_enough::<$ty>()?;
-            let v = BigEndian::$bo_method(self.input);
-            self.consume::<$ty>();
+            self.begin();
+            let v = O::Endian::$bo_method(self.take(mem::size_of::<$ty>())?.as_slice());
+            visitor.$visitor_method(v)
+        }
+    }
+}
+
+/// integers wider than a byte, `$varint` converts the decoded varint back,
+/// returning `None` if it doesn't fit
+macro_rules! impl_int {
+    ($ty:ty, $de_method:ident, $bo_method:ident, $visitor_method:ident, $varint:expr) => {
+        #[inline(always)]
+        fn $de_method<V>(self, visitor: V) -> DeResult<V::Value>
+            where V: Visitor<'de>,
+        {
+            self.begin();
+            if O::Ints::VARINT {
+                let varint: fn(u64) -> Option<$ty> = $varint;
+                let v = varint(self.consume_varint()?).ok_or(DeError::InvalidVarint)?;
+                return visitor.$visitor_method(v);
+            }
+            let v = O::Endian::$bo_method(self.take(mem::size_of::<$ty>())?.as_slice());
             visitor.$visitor_method(v)
         }
     }
 }
 
 macro_rules! not_impl {
-    ($de_method:ident) => {
+    ($de_method:ident, $kind:ident) => {
         #[inline(always)]
         fn $de_method<V>(self, _visitor: V) -> DeResult<V::Value>
             where V: Visitor<'de>,
         {
-            unimplemented!();
+            self.begin();
+            Err(DeError::Unsupported(Unsupported::$kind))
         }
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de, R: Input<'de>, O: Options> de::Deserializer<'de> for &mut Deserializer<R, O> {
     type Error = DeError;
 
     #[inline(always)]
     fn deserialize_bool<V>(self, visitor: V) -> DeResult<V::Value>
         where V: Visitor<'de>
     {
+        self.begin();
         visitor.visit_bool(self.consume_bool()?)
     }
 
@@ -108,6 +561,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_u8<V>(self, visitor: V) -> DeResult<V::Value>
         where V: Visitor<'de>
     {
+        self.begin();
         visitor.visit_u8(self.consume_u8()?)
     }
 
@@ -115,22 +569,34 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     fn deserialize_i8<V>(self, visitor: V) -> DeResult<V::Value>
         where V: Visitor<'de>
     {
+        self.begin();
         visitor.visit_i8(self.consume_u8()? as i8)
     }
 
-    impl_value!(u16, deserialize_u16, read_u16, visit_u16);
-    impl_value!(i16, deserialize_i16, read_i16, visit_i16);
-    impl_value!(u32, deserialize_u32, read_u32, visit_u32);
-    impl_value!(i32, deserialize_i32, read_i32, visit_i32);
-    impl_value!(u64, deserialize_u64, read_u64, visit_u64);
-    impl_value!(i64, deserialize_i64, read_i64, visit_i64);
+    impl_int!(u16, deserialize_u16, read_u16, visit_u16, |v| u16::try_from(v).ok());
+    impl_int!(i16, deserialize_i16, read_i16, visit_i16, |v| i16::try_from(unzigzag(v)).ok());
+    impl_int!(u32, deserialize_u32, read_u32, visit_u32, |v| u32::try_from(v).ok());
+    impl_int!(i32, deserialize_i32, read_i32, visit_i32, |v| i32::try_from(unzigzag(v)).ok());
+    impl_int!(u64, deserialize_u64, read_u64, visit_u64, Some);
+    impl_int!(i64, deserialize_i64, read_i64, visit_i64, |v| Some(unzigzag(v)));
+    impl_value!(u128, deserialize_u128, read_u128, visit_u128);
+    impl_value!(i128, deserialize_i128, read_i128, visit_i128);
     impl_value!(f32, deserialize_f32, read_f32, visit_f32);
     impl_value!(f64, deserialize_f64, read_f64, visit_f64);
 
+    #[inline(always)]
+    fn deserialize_char<V>(self, visitor: V) -> DeResult<V::Value>
+        where V: Visitor<'de>
+    {
+        self.begin();
+        visitor.visit_char(self.consume_char()?)
+    }
+
     #[inline(always)]
     fn deserialize_option<V>(self, visitor: V) -> DeResult<V::Value>
         where V: Visitor<'de>
     {
+        self.begin();
         if self.consume_bool()? {
             visitor.visit_some(self)
         } else {
@@ -159,11 +625,32 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     #[inline(always)]
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V
     ) -> DeResult<V::Value>
         where V: Visitor<'de>
     {
+        if name == varint::NAME {
+            self.begin();
+            return visitor.visit_u64(self.consume_varint()?);
+        }
+        // `Usize` and `Isize` are `O::Usize` wide
+        if name == size::USIZE_NAME {
+            let v = self.consume_usize()?;
+            return visitor.visit_newtype_struct(IntoDeserializer::<DeError>::into_deserializer(v));
+        }
+        if name == size::ISIZE_NAME {
+            let v = self.consume_isize()?;
+            return visitor.visit_newtype_struct(IntoDeserializer::<DeError>::into_deserializer(v));
+        }
+        #[cfg(feature = "half")]
+        if name == float::NAME {
+            // the bits of an `F16` are read like a float, never a varint
+            self.begin();
+            let bits = O::Endian::read_u16(self.take(2)?.as_slice());
+            let bits = IntoDeserializer::<DeError>::into_deserializer(bits);
+            return visitor.visit_newtype_struct(bits);
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -175,35 +662,44 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     ) -> DeResult<V::Value>
         where V: Visitor<'de>
     {
-        visitor.visit_seq(Tuple { deserializer: self, len: len })
+        visitor.visit_seq(Tuple { deserializer: self, len, fields: &[] })
     }
 
     #[inline(always)]
     fn deserialize_struct<V>(self,
-                       _name: &str,
+                       name: &'static str,
                        fields: &'static [&'static str],
                        visitor: V) -> DeResult<V::Value>
         where V: Visitor<'de>,
     {
-        self.deserialize_tuple(fields.len(), visitor)
+        self.push(name);
+        let value = self.deserialize_fields(fields, visitor)?;
+        self.pop();
+        Ok(value)
     }
 
     #[inline(always)]
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         variants: &'static [&'static str],
         visitor: V
     ) -> DeResult<V::Value>
         where V: Visitor<'de>
     {
-        if variants.len() > u8::MAX as usize {
-            panic!(MSG_ENUM_LARGE);
-        }
-        if self.input[0] as usize >= variants.len() {
-            return Err(DeError::InvalidVariant);
+        // indexes the tag can't hold are never encoded, there is no need to
+        // check the number of variants
+        let depth = self.path_depth();
+        // explicit tags are checked by `Tagged` itself, whose name stays out
+        // of the path
+        let checked = name != tagged::NAME;
+        if checked {
+            self.push(name);
         }
-        visitor.visit_enum(self)
+        let value = visitor.visit_enum(Enum { deserializer: &mut *self, variants, checked })?;
+        // the enum and, if `variant_seed` was called, the variant name
+        self.truncate_path(depth);
+        Ok(value)
     }
 
     #[inline(always)]
@@ -219,24 +715,80 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 
     // not supported
-    not_impl!(deserialize_identifier);
-    not_impl!(deserialize_any);
-    not_impl!(deserialize_char);
-    not_impl!(deserialize_str);
-    not_impl!(deserialize_string);
-    not_impl!(deserialize_bytes);
-    not_impl!(deserialize_byte_buf);
-    not_impl!(deserialize_seq);
-    not_impl!(deserialize_map);
-    not_impl!(deserialize_ignored_any);
-}
-
-struct Tuple<'a, 'de: 'a> {
-    deserializer: &'a mut Deserializer<'de>,
+    not_impl!(deserialize_identifier, Identifier);
+    not_impl!(deserialize_any, Any);
+    not_impl!(deserialize_map, Map);
+    not_impl!(deserialize_ignored_any, IgnoredAny);
+
+    // length prefixed, only when enabled in the options
+
+    #[inline(always)]
+    fn deserialize_str<V>(self, visitor: V) -> DeResult<V::Value>
+        where V: Visitor<'de>
+    {
+        match self.consume_bytes(Unsupported::Str)? {
+            Reference::Borrowed(bytes) => {
+                visitor.visit_borrowed_str(str::from_utf8(bytes).map_err(|_| DeError::InvalidUtf8)?)
+            }
+            Reference::Copied(bytes) => {
+                visitor.visit_str(str::from_utf8(bytes).map_err(|_| DeError::InvalidUtf8)?)
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn deserialize_string<V>(self, visitor: V) -> DeResult<V::Value>
+        where V: Visitor<'de>
+    {
+        self.deserialize_str(visitor)
+    }
+
+    #[inline(always)]
+    fn deserialize_bytes<V>(self, visitor: V) -> DeResult<V::Value>
+        where V: Visitor<'de>
+    {
+        match self.consume_bytes(Unsupported::Bytes)? {
+            Reference::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Reference::Copied(bytes) => visitor.visit_bytes(bytes),
+        }
+    }
+
+    #[inline(always)]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> DeResult<V::Value>
+        where V: Visitor<'de>
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    #[inline(always)]
+    fn deserialize_seq<V>(self, visitor: V) -> DeResult<V::Value>
+        where V: Visitor<'de>
+    {
+        let len = self.consume_len(Unsupported::Seq)?;
+        self.deserialize_tuple(len, visitor)
+    }
+}
+
+impl<'de, R: Input<'de>, O: Options> Deserializer<R, O> {
+    /// Decode the fields of a struct (or struct variant) in order.
+    #[inline(always)]
+    fn deserialize_fields<V>(&mut self,
+                             fields: &'static [&'static str],
+                             visitor: V) -> DeResult<V::Value>
+        where V: Visitor<'de>,
+    {
+        visitor.visit_seq(Tuple { deserializer: self, len: fields.len(), fields })
+    }
+}
+
+struct Tuple<'a, R: 'a, O: 'a> {
+    deserializer: &'a mut Deserializer<R, O>,
     len: usize,
+    // names of the remaining elements, empty for tuples
+    fields: &'static [&'static str],
 }
 
-impl<'a, 'de> SeqAccess<'de> for Tuple<'a, 'de> { 
+impl<'de, R: Input<'de>, O: Options> SeqAccess<'de> for Tuple<'_, R, O> {
     type Error = DeError;
 
     fn next_element_seed<T>(&mut self, seed: T) -> DeResult<Option<T::Value>>
@@ -244,8 +796,16 @@ impl<'a, 'de> SeqAccess<'de> for Tuple<'a, 'de> {
     {
         if self.len > 0 {
             self.len -= 1;
-            let value = DeserializeSeed::deserialize(seed, &mut *self.deserializer)?;
-            Ok(Some(value))
+            if let Some((field, rest)) = self.fields.split_first() {
+                self.fields = rest;
+                self.deserializer.push(field);
+                let value = DeserializeSeed::deserialize(seed, &mut *self.deserializer)?;
+                self.deserializer.pop();
+                Ok(Some(value))
+            } else {
+                let value = DeserializeSeed::deserialize(seed, &mut *self.deserializer)?;
+                Ok(Some(value))
+            }
         } else {
             Ok(None)
         }
@@ -257,20 +817,35 @@ impl<'a, 'de> SeqAccess<'de> for Tuple<'a, 'de> {
     }
 }
 
-impl<'de, 'a> EnumAccess<'de> for &'a mut Deserializer<'de> {
+struct Enum<'a, R: 'a, O: 'a> {
+    deserializer: &'a mut Deserializer<R, O>,
+    variants: &'static [&'static str],
+    // whether the tag is a variant index
+    checked: bool,
+}
+
+impl<'a, 'de, R: Input<'de>, O: Options> EnumAccess<'de> for Enum<'a, R, O> {
     type Error = DeError;
-    type Variant = Self;
+    type Variant = &'a mut Deserializer<R, O>;
 
     fn variant_seed<V>(self, seed: V) -> DeResult<(V::Value, Self::Variant)>
         where V: DeserializeSeed<'de>
     {
-        let index = self.consume_u8()?;
-        let val = seed.deserialize((index as u32).into_deserializer())?;
-        Ok((val, self))
+        let index = O::Tag::deserialize(&mut *self.deserializer)?;
+        if self.checked {
+            match self.variants.get(index as usize) {
+                Some(variant) => self.deserializer.push(variant),
+                None => return Err(DeError::InvalidVariant),
+            }
+        }
+        let val = seed.deserialize(IntoDeserializer::<DeError>::into_deserializer(index))?;
+        Ok((val, self.deserializer))
     }
 }
 
-impl<'de, 'a> VariantAccess<'de> for &'a mut Deserializer<'de> {
+// the variant name pushed by `Enum::variant_seed` is dropped by
+// `deserialize_enum` once the value has been decoded
+impl<'de, R: Input<'de>, O: Options> VariantAccess<'de> for &mut Deserializer<R, O> {
     type Error = DeError;
 
     fn unit_variant(self) -> DeResult<()> {
@@ -296,7 +871,7 @@ impl<'de, 'a> VariantAccess<'de> for &'a mut Deserializer<'de> {
                        visitor: V) -> DeResult<V::Value>
         where V: Visitor<'de>,
     {
-        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+        self.deserialize_fields(fields, visitor)
     }
 }
 
@@ -344,3 +919,570 @@ fn test_de_enum() {
     let v: E = from_bytes(&buffer).unwrap();
     assert_eq!(v, E::Struct{a: 1});
 }
+
+#[test]
+fn test_de_custom() {
+    use core::num::NonZeroU8;
+    use serde::de::Error;
+    use crate::error::Message;
+
+    fn even<'de, D: de::Deserializer<'de>>(d: D) -> Result<u8, D::Error> {
+        let v = u8::deserialize(d)?;
+        if v % 2 == 0 {
+            Ok(v)
+        } else {
+            Err(D::Error::custom(format_args!("{} is odd", v)))
+        }
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct S {
+        #[serde(deserialize_with = "even")]
+        a: u8,
+    }
+
+    assert_eq!(from_bytes::<S>(&[2]).unwrap(), S { a: 2 });
+    assert_eq!(from_bytes::<S>(&[3]).unwrap_err(), DeError::Custom(Message::new("3 is odd")));
+    assert_eq!(from_bytes::<NonZeroU8>(&[0]).unwrap_err(), DeError::InvalidValue);
+}
+
+#[test]
+fn test_de_unsupported() {
+    use std::collections::BTreeMap;
+    use serde::de::{Deserializer as _, IgnoredAny};
+    use serde_bytes::{ByteBuf, Bytes};
+
+    fn unsupported<'de, T: Deserialize<'de>>(buffer: &'de [u8]) -> DeError {
+        from_bytes::<T>(buffer).err().unwrap()
+    }
+
+    let buffer = [0u8; 16];
+    // variable length types are disabled by `DefaultOptions`
+    assert_eq!(unsupported::<&str>(&buffer), DeError::Unsupported(Unsupported::Str));
+    assert_eq!(unsupported::<String>(&buffer), DeError::Unsupported(Unsupported::Str));
+    assert_eq!(unsupported::<Bytes>(&buffer), DeError::Unsupported(Unsupported::Bytes));
+    assert_eq!(unsupported::<ByteBuf>(&buffer), DeError::Unsupported(Unsupported::Bytes));
+    assert_eq!(unsupported::<Vec<u8>>(&buffer), DeError::Unsupported(Unsupported::Seq));
+    assert_eq!(unsupported::<BTreeMap<u8, u8>>(&buffer), DeError::Unsupported(Unsupported::Map));
+    assert_eq!(unsupported::<IgnoredAny>(&buffer), DeError::Unsupported(Unsupported::IgnoredAny));
+
+    let mut deserializer = Deserializer::from_bytes(&buffer);
+    assert_eq!(deserializer.deserialize_any(IgnoredAny).unwrap_err(),
+               DeError::Unsupported(Unsupported::Any));
+    assert_eq!(deserializer.deserialize_identifier(IgnoredAny).unwrap_err(),
+               DeError::Unsupported(Unsupported::Identifier));
+}
+
+#[test]
+fn test_de_context() {
+    use core::fmt;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Gps {
+        fix: bool,
+        lat: i32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    enum Reading {
+        Empty,
+        Gps(Gps),
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Frame {
+        seq: u16,
+        reading: Reading,
+    }
+
+    let buffer = [
+        0, 7,               // seq
+        1,                  // Reading::Gps
+        2,                  // fix: invalid boolean
+        0, 0, 0, 1,         // lat
+    ];
+    let context = from_bytes_context::<Frame>(&buffer).unwrap_err();
+    assert_eq!(context.error, DeError::ExpectedBoolean);
+    assert_eq!(context.offset, 3);
+    #[cfg(feature = "path")]
+    assert_eq!(context.path.to_string(), "Frame.reading.Reading.Gps.Gps.fix");
+
+    let buffer = [0, 7, 1, 1, 0, 0];
+    let context = from_bytes_context::<Frame>(&buffer).unwrap_err();
+    assert_eq!(context.error, DeError::BufferSmall);
+    assert_eq!(context.offset, 4);
+    #[cfg(feature = "path")]
+    assert_eq!(context.path.to_string(), "Frame.reading.Reading.Gps.Gps.lat");
+
+    let context = from_bytes_context::<Frame>(&[0, 7, 0, 9]).unwrap_err();
+    assert_eq!(context.error, DeError::BufferLarge);
+    assert_eq!(context.offset, 3);
+    #[cfg(feature = "path")]
+    assert!(context.path.is_empty());
+
+    // an enum visitor that never asks for the variant
+    #[derive(Debug)]
+    struct Skip;
+
+    impl<'de> Deserialize<'de> for Skip {
+        fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Skip, D::Error> {
+            struct SkipVisitor;
+
+            impl<'de> Visitor<'de> for SkipVisitor {
+                type Value = Skip;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("an enum")
+                }
+
+                fn visit_enum<A: EnumAccess<'de>>(self, _data: A) -> Result<Skip, A::Error> {
+                    Ok(Skip)
+                }
+            }
+
+            deserializer.deserialize_enum("Skip", &["A"], SkipVisitor)
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Skipped {
+        skip: Skip,
+        fix: u16,
+    }
+
+    let context = from_bytes_context::<Skipped>(&[0]).unwrap_err();
+    assert_eq!(context.error, DeError::BufferSmall);
+    #[cfg(feature = "path")]
+    assert_eq!(context.path.to_string(), "Skipped.fix");
+}
+
+#[test]
+fn test_de_offsets() {
+    use core::num::NonZeroU8;
+    use crate::options::{Config, U8};
+    use crate::varint::Varint;
+    use serde::de::{DeserializeOwned, Error, Unexpected};
+    use crate::error::Message;
+
+    #[derive(Debug, Deserialize)]
+    enum E {
+        A,
+    }
+
+    // a `u8` that is then rejected with error number `K`
+    struct Rejected<const K: u8>;
+
+    impl<'de, const K: u8> Deserialize<'de> for Rejected<K> {
+        fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let v = u8::deserialize(d)?;
+            Err(match K {
+                0 => D::Error::custom("rejected"),
+                1 => D::Error::invalid_type(Unexpected::Unsigned(v.into()), &"a bool"),
+                2 => D::Error::invalid_value(Unexpected::Unsigned(v.into()), &"zero"),
+                3 => D::Error::invalid_length(v.into(), &"no elements"),
+                4 => D::Error::unknown_variant("A", &[]),
+                _ => D::Error::missing_field("a"),
+            })
+        }
+    }
+
+    // the offset always points at the start of the value that failed, after
+    // the `u16` in front of it
+    fn offset<'de, T: Deserialize<'de>>(buffer: &'de [u8]) -> (DeError, usize) {
+        let context = from_bytes_context::<(u16, T)>(buffer).err().unwrap();
+        (context.error, context.offset)
+    }
+
+    assert_eq!(offset::<u32>(&[0, 0, 1, 2, 3]), (DeError::BufferSmall, 2));
+    assert_eq!(offset::<u8>(&[0, 0, 1, 2]), (DeError::BufferLarge, 3));
+    assert_eq!(offset::<bool>(&[0, 0, 2]), (DeError::ExpectedBoolean, 2));
+    assert_eq!(offset::<Option<u8>>(&[0, 0, 2]), (DeError::ExpectedBoolean, 2));
+    assert_eq!(offset::<Option<u32>>(&[0, 0, 1, 0]), (DeError::BufferSmall, 3));
+    assert_eq!(offset::<E>(&[0, 0, 1]), (DeError::InvalidVariant, 2));
+    assert_eq!(offset::<NonZeroU8>(&[0, 0, 0]), (DeError::InvalidValue, 2));
+    assert_eq!(offset::<char>(&[0, 0, 0xFF]), (DeError::InvalidChar, 2));
+    assert_eq!(offset::<char>(&[0, 0, 0xC0, 0x80]), (DeError::InvalidChar, 2));
+    assert_eq!(offset::<char>(&[0, 0, 0xE2, 0x82]), (DeError::BufferSmall, 2));
+    assert_eq!(offset::<Rejected<0>>(&[0, 0, 1]),
+               (DeError::Custom(Message::new("rejected")), 2));
+    assert_eq!(offset::<Rejected<1>>(&[0, 0, 1]), (DeError::InvalidType, 2));
+    assert_eq!(offset::<Rejected<2>>(&[0, 0, 1]), (DeError::InvalidValue, 2));
+    assert_eq!(offset::<Rejected<3>>(&[0, 0, 1]), (DeError::InvalidLength(1), 2));
+    assert_eq!(offset::<Rejected<4>>(&[0, 0, 1]), (DeError::UnknownVariant, 2));
+    assert_eq!(offset::<Rejected<5>>(&[0, 0, 1]), (DeError::MissingField("a"), 2));
+
+    // length prefixed values start at the prefix
+    fn str_offset(buffer: &[u8]) -> (DeError, usize) {
+        let options = Config::new().with_length::<U8, 4>();
+        let context = from_bytes_context_with::<_, (u16, &str)>(options, buffer).err().unwrap();
+        (context.error, context.offset)
+    }
+
+    assert_eq!(offset::<&str>(&[0, 0, 0]), (DeError::Unsupported(Unsupported::Str), 2));
+    assert_eq!(str_offset(&[0, 0, 5, 0, 0, 0, 0, 0]), (DeError::LengthLarge, 2));
+    assert_eq!(str_offset(&[0, 0, 3, b'a']), (DeError::BufferSmall, 2));
+    assert_eq!(str_offset(&[0, 0, 1, 0xFF]), (DeError::InvalidUtf8, 2));
+    assert_eq!(str_offset(&[0, 0]), (DeError::BufferSmall, 2));
+
+    // varints start at their first byte
+    fn varint_offset<T: DeserializeOwned>(buffer: &[u8]) -> (DeError, usize) {
+        let options = Config::new().with_varint_encoding();
+        let context = from_bytes_context_with::<_, (u16, T)>(options, buffer).err().unwrap();
+        (context.error, context.offset)
+    }
+
+    assert_eq!(offset::<Varint<u16>>(&[0, 0, 0xFF, 0xFF, 0x04]), (DeError::InvalidVarint, 2));
+    let overlong = [0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    assert_eq!(offset::<Varint<u64>>(&overlong), (DeError::InvalidVarint, 2));
+    assert_eq!(offset::<Varint<u64>>(&[0, 0, 0xFF, 0xFF]), (DeError::BufferSmall, 2));
+    assert_eq!(varint_offset::<u16>(&[1, 0xFF, 0xFF, 0x04]), (DeError::InvalidVarint, 1));
+    assert_eq!(varint_offset::<u32>(&[1, 0xFF]), (DeError::BufferSmall, 1));
+
+    // so do the bits of an `F16`, even with varints
+    #[cfg(feature = "half")]
+    assert_eq!(varint_offset::<crate::float::F16>(&[1, 0]), (DeError::BufferSmall, 1));
+}
+
+#[test]
+fn test_de_length_prefixed() {
+    use crate::options::{Config, U8, U16};
+    use serde_bytes::Bytes;
+
+    let options = Config::new().with_length::<U8, 4>();
+    let buffer = [3, b'a', b'b', b'c'];
+    let v: &str = from_bytes_with(options, &buffer).unwrap();
+    assert_eq!(v, "abc");
+    assert_eq!(v.as_ptr(), buffer[1..].as_ptr());
+    let v: String = from_bytes_with(options, &buffer).unwrap();
+    assert_eq!(v, "abc");
+    let v: Bytes = from_bytes_with(options, &buffer).unwrap();
+    assert_eq!(&*v, b"abc");
+    let v: Vec<u16> = from_bytes_with(options, &[2, 0, 1, 0, 2]).unwrap();
+    assert_eq!(v, [1, 2]);
+
+    assert_eq!(from_bytes_with::<_, &str>(options, &[5, 0, 0, 0, 0, 0]).unwrap_err(),
+               DeError::LengthLarge);
+    assert_eq!(from_bytes_with::<_, &str>(options, &[3, b'a']).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes_with::<_, &str>(options, &[1, 0xFF]).unwrap_err(), DeError::InvalidUtf8);
+
+    let options = Config::new().with_length::<U16, 1000>();
+    let v: (u8, &str) = from_bytes_with(options, &[1, 0, 2, b'h', b'i']).unwrap();
+    assert_eq!(v, (1, "hi"));
+}
+
+#[test]
+fn test_de_options() {
+    use crate::options::{Config, U16, U32};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum E {
+        A,
+        B(u16),
+    }
+
+    let options = Config::new().with_little_endian();
+    let v: (u16, i32, f32) = from_bytes_with(options,
+        &[2, 1, 0xFE, 0xFF, 0xFF, 0xFF, 0, 0, 0x80, 0x3F]).unwrap();
+    assert_eq!(v, (0x0102, -2, 1.0));
+
+    let options = Config::new().with_tag::<U16>();
+    let v: (E, E, Option<u8>) = from_bytes_with(options, &[0, 0, 0, 1, 0, 3, 1, 4]).unwrap();
+    assert_eq!(v, (E::A, E::B(3), Some(4)));
+    assert_eq!(from_bytes_with::<_, E>(options, &[1, 0]).unwrap_err(), DeError::InvalidVariant);
+
+    let options = Config::new().with_little_endian().with_tag::<U32>();
+    assert_eq!(from_bytes_with::<_, E>(options, &[1, 0, 0, 0, 3, 0]).unwrap(), E::B(3));
+    assert_eq!(from_bytes_with::<_, E>(options, &[1, 0, 0]).unwrap_err(), DeError::BufferSmall);
+}
+
+#[test]
+fn test_de_varint() {
+    use crate::options::{Config, U16};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum E<'a> {
+        A(u8, u16, i32, u64),
+        B(&'a str),
+    }
+
+    let options = Config::new().with_varint_encoding().with_little_endian();
+    let v: (u16, u32, i64, f32) = from_bytes_with(options,
+        &[1, 0xAC, 0x02, 1, 0, 0, 0x80, 0x3F]).unwrap();
+    assert_eq!(v, (1, 300, -1, 1.0));
+
+    let options = options.with_tag::<U16>().with_length::<U16, 200>();
+    let v: E = from_bytes_with(options, &[0, 0xFF, 0xFF, 0x01, 0x81, 0x01,
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]).unwrap();
+    assert_eq!(v, E::A(0xFF, 0xFF, -65, u64::MAX));
+    assert_eq!(from_bytes_with::<_, E>(options, &[1, 2, b'h', b'i']).unwrap(), E::B("hi"));
+
+    assert_eq!(from_bytes_with::<_, u16>(options, &[0xFF, 0xFF, 0x04]).unwrap_err(),
+               DeError::InvalidVarint);
+    assert_eq!(from_bytes_with::<_, u64>(options, &[0xFF; 11]).unwrap_err(),
+               DeError::InvalidVarint);
+    assert_eq!(from_bytes_with::<_, u32>(options, &[0x80]).unwrap_err(), DeError::BufferSmall);
+}
+
+#[test]
+fn test_de_wide_and_char() {
+    use crate::options::Config;
+
+    let mut buffer = [0xFFu8; 32];
+    buffer[15] = 0xFE;
+    buffer[31] = 0xFE;
+    let v: (u128, i128) = from_bytes(&buffer).unwrap();
+    assert_eq!(v, (u128::MAX - 1, -2));
+    let options = Config::new().with_little_endian().with_varint_encoding();
+    let mut buffer = [0u8; 16];
+    buffer[0] = 1;
+    assert_eq!(from_bytes_with::<_, u128>(options, &buffer).unwrap(), 1);
+    assert_eq!(from_bytes::<u128>(&buffer[..15]).unwrap_err(), DeError::BufferSmall);
+
+    let v: (char, char, char, char) = from_bytes("A\u{e9}\u{20ac}\u{1f980}".as_bytes()).unwrap();
+    assert_eq!(v, ('A', '\u{e9}', '\u{20ac}', '\u{1f980}'));
+
+    assert_eq!(from_bytes::<char>(&[]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<char>(&[0xF0, 0x9F, 0xA6]).unwrap_err(), DeError::BufferSmall);
+    // continuation byte, surrogate, overlong and too large scalars
+    assert_eq!(from_bytes::<char>(&[0x80]).unwrap_err(), DeError::InvalidChar);
+    assert_eq!(from_bytes::<char>(&[0xED, 0xA0, 0x80]).unwrap_err(), DeError::InvalidChar);
+    assert_eq!(from_bytes::<char>(&[0xC0, 0x80]).unwrap_err(), DeError::InvalidChar);
+    assert_eq!(from_bytes::<char>(&[0xF4, 0x90, 0x80, 0x80]).unwrap_err(), DeError::InvalidChar);
+    assert_eq!(from_bytes::<char>(&[0xFF]).unwrap_err(), DeError::InvalidChar);
+}
+
+#[test]
+fn test_de_enum_large() {
+    use core::fmt;
+    use crate::options::{Config, U16, VarintTag};
+
+    // a unit only enum with 400 variants
+    #[derive(Debug, PartialEq)]
+    struct Variant(u32);
+
+    impl<'de> Deserialize<'de> for Variant {
+        fn deserialize<D: de::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            struct VariantVisitor;
+            impl<'de> Visitor<'de> for VariantVisitor {
+                type Value = Variant;
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a command")
+                }
+                fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Variant, A::Error> {
+                    let (index, variant) = data.variant::<u32>()?;
+                    variant.unit_variant()?;
+                    Ok(Variant(index))
+                }
+            }
+            static VARIANTS: [&str; 400] = ["command"; 400];
+            d.deserialize_enum("Command", &VARIANTS, VariantVisitor)
+        }
+    }
+
+    assert_eq!(from_bytes::<Variant>(&[255]).unwrap(), Variant(255));
+
+    let options = Config::new().with_tag::<U16>();
+    assert_eq!(from_bytes_with::<_, Variant>(options, &[0x01, 0x8F]).unwrap(), Variant(399));
+    assert_eq!(from_bytes_with::<_, Variant>(options, &[0x01, 0x90]).unwrap_err(),
+               DeError::InvalidVariant);
+
+    let options = Config::new().with_tag::<VarintTag>();
+    assert_eq!(from_bytes_with::<_, Variant>(options, &[0x8F, 0x03]).unwrap(), Variant(399));
+    assert_eq!(from_bytes_with::<_, Variant>(options, &[0x90, 0x03]).unwrap_err(),
+               DeError::InvalidVariant);
+    // reported at the start of the tag
+    let context = from_bytes_context_with::<_, (u8, Variant)>(options, &[0, 0x90, 0x03]);
+    let context = context.unwrap_err();
+    assert_eq!((context.error, context.offset), (DeError::InvalidVariant, 1));
+}
+
+#[test]
+fn test_de_empty() {
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    enum E {
+        Unit,
+        Newtype(u8),
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct S {
+        a: u8,
+        e: E,
+    }
+
+    assert_eq!(from_bytes::<E>(&[]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<E>(&[1]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<Option<u8>>(&[]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<Option<u8>>(&[1]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<(u8, u16)>(&[]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<S>(&[]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<S>(&[1]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<[u32; 2]>(&[0, 0, 0, 0, 0]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(from_bytes::<()>(&[]).unwrap(), ());
+}
+
+/// Decode `bytes` as a representative set of types with several options, none
+/// of which may panic.
+#[cfg(test)]
+fn decode_arbitrary(bytes: &[u8]) {
+    use std::collections::BTreeMap;
+    use crate::options::{Config, U8, U16, U32, VarintTag};
+    use serde_bytes::Bytes;
+    use crate::size::{Isize, Usize};
+    use crate::tagged::Tagged;
+    use crate::varint::Varint;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    enum E<'a> {
+        Unit,
+        Newtype(u32),
+        Tuple(i8, Option<u16>),
+        Struct { a: bool, b: [i16; 3], c: char },
+        Str(&'a str),
+        Seq(#[serde(borrow)] Vec<E<'a>>),
+    }
+
+    #[derive(Deserialize, EnumTags)]
+    #[allow(dead_code)]
+    enum Command {
+        #[ubyte(tag = 0x42)]
+        Stop,
+        #[ubyte(tag = 7)]
+        Move { x: i16, y: i16 },
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Frame<'a> {
+        seq: u16,
+        #[serde(borrow)]
+        e: E<'a>,
+        command: Tagged<Command>,
+        big: (u128, i128, f32, f64),
+        counter: Varint<u64>,
+        rssi: Varint<i16>,
+        sizes: (Usize, Isize),
+        data: Option<Bytes<'a>>,
+    }
+
+    fn decode<O: Options>(options: O, bytes: &[u8]) {
+        let _ = from_bytes_with::<_, E>(options, bytes);
+        let _ = from_bytes_with::<_, Frame>(options, bytes);
+        let _ = from_bytes_with::<_, Option<(u8, u64, bool)>>(options, bytes);
+        let _ = from_bytes_with::<_, String>(options, bytes);
+        let _ = from_bytes_with::<_, Vec<Option<char>>>(options, bytes);
+        let _ = from_bytes_with::<_, BTreeMap<u8, u8>>(options, bytes);
+        let _ = from_bytes_context_with::<_, Frame>(options, bytes);
+    }
+
+    decode(Config::new(), bytes);
+    decode(Config::new().with_length::<U8, 16>(), bytes);
+    decode(Config::new().with_little_endian().with_tag::<U16>().with_length::<U16, 300>(), bytes);
+    decode(Config::new().with_tag::<VarintTag>().with_length::<U8, 255>(), bytes);
+    decode(Config::new().with_usize::<U16>().with_length::<U32, 1000>(), bytes);
+    decode(Config::new().with_varint_encoding().with_tag::<U16>().with_length::<U16, 64>(), bytes);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_de_arbitrary_bytes(bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..96)) {
+        decode_arbitrary(&bytes);
+    }
+}
+
+#[test]
+fn test_de_take_and_iter() {
+    use crate::options::{Config, U8};
+
+    let bytes = [0, 1, 2, 3, 4];
+    let (v, rest) = take_from_bytes::<u16>(&bytes).unwrap();
+    assert_eq!(v, 1);
+    assert_eq!(rest, &[2, 3, 4]);
+    let (v, rest) = take_from_bytes::<(u8, u16)>(rest).unwrap();
+    assert_eq!(v, (2, 0x304));
+    assert!(rest.is_empty());
+    assert_eq!(take_from_bytes::<u16>(&[1]).unwrap_err(), DeError::BufferSmall);
+
+    let options = Config::new().with_length::<U8, 8>();
+    let bytes = [2, b'h', b'i', 0, 7];
+    let (v, rest) = take_from_bytes_with::<_, &str>(options, &bytes).unwrap();
+    assert_eq!(v, "hi");
+    assert_eq!(rest, &[0, 7]);
+
+    let mut deserializer = Deserializer::from_bytes(&bytes);
+    assert!(!deserializer.is_empty());
+    assert_eq!(u8::deserialize(&mut deserializer).unwrap(), 2);
+    assert_eq!(deserializer.remaining(), &bytes[1..]);
+    assert_eq!(<[u8; 4]>::deserialize(&mut deserializer).unwrap(), [b'h', b'i', 0, 7]);
+    assert!(deserializer.is_empty());
+
+    let values: Vec<_> = iter_from_bytes::<u16>(&[0, 1, 0, 2, 0, 3]).collect();
+    assert_eq!(values, vec![Ok(1), Ok(2), Ok(3)]);
+    assert_eq!(iter_from_bytes::<u16>(&[]).next(), None);
+
+    // stops after the first error
+    let mut iter = iter_from_bytes::<bool>(&[1, 0, 2, 1]);
+    assert_eq!(iter.next(), Some(Ok(true)));
+    assert_eq!(iter.next(), Some(Ok(false)));
+    assert_eq!(iter.offset(), 2);
+    assert_eq!(iter.next(), Some(Err(DeError::ExpectedBoolean)));
+    assert_eq!(iter.next(), None);
+    // the rejected byte was decoded
+    assert_eq!(iter.remaining(), &[1]);
+
+    let mut iter = iter_from_bytes_with::<_, &str>(options, &[1, b'a', 3, b'b']);
+    assert_eq!(iter.next(), Some(Ok("a")));
+    assert_eq!(iter.next(), Some(Err(DeError::BufferSmall)));
+    assert_eq!(iter.next(), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_de_from_reader() {
+    use std::io::Read;
+    use crate::options::{Config, U8};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Record {
+        id: u16,
+        name: String,
+        tag: Option<char>,
+    }
+
+    let options = Config::new().with_length::<U8, 16>();
+    let bytes = [0, 1, 2, b'h', b'i', 1, 0xC3, 0xA9, 0, 2, 0, 0, 0xAA, 0xBB];
+    let mut reader = &bytes[..];
+    let record: Record = from_reader_with(options, &mut reader).unwrap();
+    assert_eq!(record, Record { id: 1, name: "hi".to_string(), tag: Some('\u{e9}') });
+    let record: Record = from_reader_with(options, &mut reader).unwrap();
+    assert_eq!(record, Record { id: 2, name: String::new(), tag: None });
+    // the rest of the stream is untouched
+    assert_eq!(reader, &[0xAA, 0xBB]);
+    assert_eq!(from_reader::<_, u8>(&mut reader).unwrap(), 0xAA);
+    assert_eq!(from_reader::<_, u16>(&mut reader).unwrap_err(), DeError::BufferSmall);
+
+    // other io errors are passed on
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> ::std::io::Result<usize> {
+            Err(::std::io::ErrorKind::ConnectionReset.into())
+        }
+    }
+    assert_eq!(from_reader::<_, u8>(Broken).unwrap_err(),
+               DeError::Io(::std::io::ErrorKind::ConnectionReset));
+
+    // a reader has nothing to borrow from
+    let input = [2, b'o', b'k'];
+    let mut deserializer = Deserializer::with_input(options, ReaderInput::new(&input[..]));
+    assert_eq!(<&str>::deserialize(&mut deserializer).unwrap_err(), DeError::InvalidType);
+    let mut deserializer = Deserializer::with_input(options, ReaderInput::new(&input[..]));
+    assert_eq!(String::deserialize(&mut deserializer).unwrap(), "ok");
+}
@@ -1,7 +1,6 @@
 
 // core modules
 pub use core::mem;
-pub use core::slice;
 
 // local error/result
-pub use error::{MSG_ENUM_LARGE, SerError, SerResult, DeError, DeResult};
+pub use crate::error::{SerError, SerResult, DeError, DeResult, Unsupported};
@@ -0,0 +1,394 @@
+//! `embedded_io` adapters
+//!
+//! `to_writer` serializes a value straight into any `embedded_io::Write`, e.g.
+//! a UART driver, as it is encoded, and `from_reader` reads only the bytes a
+//! value takes up from any `embedded_io::Read`, through a small scratch buffer
+//! held by the `ReadInput`.
+//!
+//! With the `embedded-io-async` feature `to_async_writer` and
+//! `from_async_reader` do the same for `embedded_io_async`. serde can't
+//! suspend in the middle of a value, so these encode into (or decode from) a
+//! buffer of `N` bytes held by the future: the writer only sees the value
+//! once all of it is encoded, and decoding starts over from the beginning of
+//! the value whenever it needs more bytes.
+
+use crate::dev_prefix::*;
+
+use embedded_io::{Error, Read, ReadExactError, Write};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::de::{Deserializer, Input, Reference};
+use crate::options::{DefaultOptions, Options};
+use crate::ser::{Output, Serializer};
+
+fn write_error<E: Error>(err: E) -> SerError {
+    SerError::EmbeddedIo(err.kind())
+}
+
+fn read_error<E: Error>(err: ReadExactError<E>) -> DeError {
+    match err {
+        ReadExactError::UnexpectedEof => DeError::BufferSmall,
+        ReadExactError::Other(err) => DeError::EmbeddedIo(err.kind()),
+    }
+}
+
+/// Output to an `embedded_io::Write`. Bytes are passed on as they are
+/// encoded.
+pub struct WriteOutput<W> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: Write> WriteOutput<W> {
+    /// Write to `writer`.
+    pub fn new(writer: W) -> Self {
+        WriteOutput { writer, written: 0 }
+    }
+
+    /// Take back the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Output for WriteOutput<W> {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        self.writer.write_all(v).map_err(write_error)?;
+        self.written += v.len();
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.written
+    }
+}
+
+/// Input from an `embedded_io::Read`, only reading the bytes that are needed.
+///
+/// Each value is read into a scratch buffer of `N` bytes, which must hold the
+/// widest number (up to 16 bytes) as well as the longest string or byte
+/// slice. Longer ones fail with `LengthLarge`, without reading them.
+pub struct ReadInput<R, const N: usize = 64> {
+    reader: R,
+    // the bytes of the last `take`
+    scratch: [u8; N],
+}
+
+impl<R: Read, const N: usize> ReadInput<R, N> {
+    /// Read from `reader`.
+    pub fn new(reader: R) -> Self {
+        ReadInput { reader, scratch: [0; N] }
+    }
+
+    /// Take back the reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<'de, R: Read, const N: usize> Input<'de> for ReadInput<R, N> {
+    #[inline(always)]
+    fn take(&mut self, len: usize) -> DeResult<Reference<'de, '_>> {
+        let scratch = self.scratch.get_mut(..len).ok_or(DeError::LengthLarge)?;
+        self.reader.read_exact(scratch).map_err(read_error)?;
+        Ok(Reference::Copied(scratch))
+    }
+}
+
+/// serialize the value to `writer` and return the number of bytes written.
+pub fn to_writer<W, T>(writer: W, value: &T) -> SerResult<usize>
+    where W: Write,
+          T: Serialize + ?Sized
+{
+    to_writer_with(DefaultOptions::new(), writer, value)
+}
+
+/// Same as `to_writer` but using the encoding `options`.
+pub fn to_writer_with<O, W, T>(options: O, writer: W, value: &T) -> SerResult<usize>
+    where O: Options,
+          W: Write,
+          T: Serialize + ?Sized
+{
+    Serializer::with_output(options, WriteOutput::new(writer)).write(value)
+}
+
+/// Deserialize a `T` from `reader`, reading only the bytes it takes up
+/// through a `ReadInput<R, N>`. The rest of the stream is left for the next
+/// value.
+pub fn from_reader<const N: usize, R, T>(reader: R) -> DeResult<T>
+    where R: Read,
+          T: DeserializeOwned
+{
+    from_reader_with::<N, _, _, _>(DefaultOptions::new(), reader)
+}
+
+/// Same as `from_reader` but using the encoding `options`.
+pub fn from_reader_with<const N: usize, O, R, T>(options: O, reader: R) -> DeResult<T>
+    where O: Options,
+          R: Read,
+          T: DeserializeOwned
+{
+    let input: ReadInput<R, N> = ReadInput::new(reader);
+    T::deserialize(&mut Deserializer::with_input(options, input))
+}
+
+/// serialize the value into a buffer of `N` bytes, then write it to `writer`
+/// and return the number of bytes written.
+///
+/// Nothing is written until the whole value is encoded, so `N` must hold all
+/// of it (e.g. `T::MAX_SIZE`); a larger value fails with `Overflow`.
+#[cfg(feature = "embedded-io-async")]
+pub async fn to_async_writer<const N: usize, W, T>(writer: W, value: &T) -> SerResult<usize>
+    where W: embedded_io_async::Write,
+          T: Serialize + ?Sized
+{
+    to_async_writer_with::<N, _, _, _>(DefaultOptions::new(), writer, value).await
+}
+
+/// Same as `to_async_writer` but using the encoding `options`.
+#[cfg(feature = "embedded-io-async")]
+pub async fn to_async_writer_with<const N: usize, O, W, T>(options: O, mut writer: W, value: &T)
+    -> SerResult<usize>
+    where O: Options,
+          W: embedded_io_async::Write,
+          T: Serialize + ?Sized
+{
+    let mut buffer = [0u8; N];
+    let len = crate::ser::to_bytes_with(options, &mut buffer, value)?;
+    writer.write_all(&buffer[..len]).await.map_err(write_error)?;
+    Ok(len)
+}
+
+/// Deserialize a `T` from `reader`, reading only the bytes it takes up into a
+/// buffer of `N` bytes.
+///
+/// The end of a value is only known once it decodes, so the bytes are read
+/// as the decoder asks for them and decoding starts over after every read:
+/// a value made of `k` numbers, strings and the like is decoded up to `k`
+/// times, which is quadratic in `k`. A value longer than `N` bytes fails with
+/// `LengthLarge`.
+///
+/// Running out of bytes is noticed through `DeError::BufferSmall`, so this
+/// relies on the `Deserialize` impls of `T` passing that error on unchanged.
+/// An impl that replaces it (e.g. with a custom error) ends decoding with its
+/// own error instead of reading more.
+#[cfg(feature = "embedded-io-async")]
+pub async fn from_async_reader<const N: usize, R, T>(reader: R) -> DeResult<T>
+    where R: embedded_io_async::Read,
+          T: DeserializeOwned
+{
+    from_async_reader_with::<N, _, _, _>(DefaultOptions::new(), reader).await
+}
+
+/// Same as `from_async_reader` but using the encoding `options`.
+#[cfg(feature = "embedded-io-async")]
+pub async fn from_async_reader_with<const N: usize, O, R, T>(options: O, mut reader: R)
+    -> DeResult<T>
+    where O: Options,
+          R: embedded_io_async::Read,
+          T: DeserializeOwned
+{
+    let mut buffer = [0u8; N];
+    let mut len = 0;
+    loop {
+        let input = PartialInput { bytes: &buffer[..len], pos: 0, needed: 0 };
+        let mut deserializer = Deserializer::with_input(options, input);
+        let needed = match T::deserialize(&mut deserializer) {
+            Err(DeError::BufferSmall) => deserializer.into_input().needed,
+            result => return result,
+        };
+        let more = buffer.get_mut(len..needed).ok_or(DeError::LengthLarge)?;
+        reader.read_exact(more).await.map_err(read_error)?;
+        len = needed;
+    }
+}
+
+/// Input from the bytes of a value read so far, noting how many the value
+/// needs at least when they run out.
+#[cfg(feature = "embedded-io-async")]
+struct PartialInput<'de> {
+    bytes: &'de [u8],
+    pos: usize,
+    needed: usize,
+}
+
+#[cfg(feature = "embedded-io-async")]
+impl<'de> Input<'de> for PartialInput<'de> {
+    #[inline(always)]
+    fn take(&mut self, len: usize) -> DeResult<Reference<'de, '_>> {
+        let end = self.pos + len;
+        let bytes = self.bytes;
+        match bytes.get(self.pos..end) {
+            Some(taken) => {
+                self.pos = end;
+                Ok(Reference::Borrowed(taken))
+            }
+            None => {
+                self.needed = end;
+                Err(DeError::BufferSmall)
+            }
+        }
+    }
+}
+
+/// A stream that moves at most `chunk` bytes per call, like a driver with a
+/// small FIFO, and takes no more than `limit` bytes.
+#[cfg(test)]
+struct Chunked {
+    bytes: Vec<u8>,
+    pos: usize,
+    chunk: usize,
+    limit: usize,
+}
+
+#[cfg(test)]
+impl Chunked {
+    fn new(bytes: &[u8], chunk: usize) -> Chunked {
+        Chunked { bytes: bytes.to_vec(), pos: 0, chunk, limit: usize::MAX }
+    }
+}
+
+#[cfg(test)]
+impl embedded_io::ErrorType for Chunked {
+    type Error = embedded_io::ErrorKind;
+}
+
+#[cfg(test)]
+impl Read for Chunked {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.chunk).min(self.bytes.len() - self.pos);
+        buf[..len].copy_from_slice(&self.bytes[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+impl Write for Chunked {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.bytes.len() == self.limit {
+            return Err(embedded_io::ErrorKind::WriteZero);
+        }
+        let len = buf.len().min(self.chunk).min(self.limit - self.bytes.len());
+        self.bytes.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+#[cfg(test)]
+impl embedded_io_async::Read for Chunked {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Read::read(self, buf)
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+#[cfg(test)]
+impl embedded_io_async::Write for Chunked {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Write::write(self, buf)
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading<'a> {
+    channel: u8,
+    value: i32,
+    unit: &'a str,
+}
+
+#[test]
+fn test_embedded_io() {
+    use crate::options::{Config, U8};
+
+    let options = Config::new().with_length::<U8, 16>();
+    let reading = Reading { channel: 3, value: -2, unit: "mV" };
+    let expected = [3, 0xFF, 0xFF, 0xFF, 0xFE, 2, b'm', b'V'];
+    for chunk in 1..10 {
+        let mut writer = Chunked::new(&[], chunk);
+        assert_eq!(to_writer_with(options, &mut writer, &reading).unwrap(), 8);
+        assert_eq!(writer.bytes, expected);
+
+        let mut stream = expected.to_vec();
+        stream.push(7);
+        let mut reader = Chunked::new(&stream, chunk);
+        let input: ReadInput<_, 4> = ReadInput::new(&mut reader);
+        let mut deserializer = Deserializer::with_input(options, input);
+        let (channel, value, unit): (u8, i32, ::std::string::String) =
+            serde::Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!((channel, value, &unit[..]), (3, -2, "mV"));
+        assert_eq!(from_reader::<4, _, u8>(&mut reader).unwrap(), 7);
+        assert_eq!(from_reader::<4, _, u8>(&mut reader).unwrap_err(), DeError::BufferSmall);
+    }
+
+    // the scratch buffer is too small for the string
+    let mut reader = Chunked::new(&[3, 0, 0, 0, 0, 5, b'v', b'o', b'l', b't', b's'], 3);
+    let input: ReadInput<_, 4> = ReadInput::new(&mut reader);
+    let mut deserializer = Deserializer::with_input(options, input);
+    let result: DeResult<(u8, i32, ::std::string::String)> =
+        serde::Deserialize::deserialize(&mut deserializer);
+    assert_eq!(result.unwrap_err(), DeError::LengthLarge);
+    assert_eq!(reader.pos, 6);
+    let mut reader = Chunked::new(&[0, 0, 0, 7], 4);
+    assert_eq!(from_reader::<2, _, u32>(&mut reader).unwrap_err(), DeError::LengthLarge);
+    assert_eq!(reader.pos, 0);
+
+    // the writer fills up partway through
+    let mut writer = Chunked::new(&[], 1);
+    writer.limit = 2;
+    assert_eq!(to_writer(&mut writer, &0u32).unwrap_err(),
+               SerError::EmbeddedIo(embedded_io::ErrorKind::WriteZero));
+    assert_eq!(writer.bytes, [0, 0]);
+}
+
+#[cfg(feature = "embedded-io-async")]
+#[test]
+fn test_embedded_io_async() {
+    use crate::options::{Config, U8};
+    use futures::executor::block_on;
+
+    let options = Config::new().with_length::<U8, 16>();
+    let reading = Reading { channel: 3, value: -2, unit: "mV" };
+    for chunk in 1..10 {
+        let mut writer = Chunked::new(&[], chunk);
+        let len = block_on(to_async_writer_with::<16, _, _, _>(options, &mut writer, &reading));
+        assert_eq!(len.unwrap(), 8);
+        block_on(to_async_writer::<4, _, _>(&mut writer, &7u32)).unwrap();
+
+        let mut reader = Chunked::new(&writer.bytes, chunk);
+        let value: (u8, i32, ::std::string::String) =
+            block_on(from_async_reader_with::<16, _, _, _>(options, &mut reader)).unwrap();
+        assert_eq!((value.0, value.1, &value.2[..]), (3, -2, "mV"));
+        assert_eq!(block_on(from_async_reader::<4, _, u32>(&mut reader)).unwrap(), 7);
+        assert_eq!(block_on(from_async_reader::<4, _, u32>(&mut reader)).unwrap_err(),
+                   DeError::BufferSmall);
+    }
+
+    // the buffers are too small for the value
+    let mut writer = Chunked::new(&[], 4);
+    assert_eq!(block_on(to_async_writer::<3, _, _>(&mut writer, &7u32)).unwrap_err(),
+               SerError::Overflow);
+    let mut reader = Chunked::new(&[0, 0, 0, 7], 4);
+    assert_eq!(block_on(from_async_reader::<3, _, u32>(&mut reader)).unwrap_err(),
+               DeError::LengthLarge);
+    assert_eq!(reader.pos, 0);
+
+    // the string is read as a whole once its length is known, nothing past it
+    let long = Reading { channel: 1, value: 2, unit: "millivolts/sec" };
+    let mut writer = Chunked::new(&[], 64);
+    let len = block_on(to_async_writer_with::<64, _, _, _>(options, &mut writer, &long)).unwrap();
+    let mut reader = Chunked::new(&writer.bytes, 64);
+    let value: (u8, i32, ::std::string::String) =
+        block_on(from_async_reader_with::<64, _, _, _>(options, &mut reader)).unwrap();
+    assert_eq!((value.0, value.1, &value.2[..]), (1, 2, long.unit));
+    assert_eq!(reader.pos, len);
+}
@@ -4,20 +4,53 @@ use core::result::Result as StdResult;
 pub use core::fmt;
 pub use core::fmt::Write;
 
-pub const MSG_ENUM_LARGE: &str = "enum > u8::MAX";
+use serde::de::{Expected, Unexpected};
+
+use crate::{tagged, varint};
+
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::string::String;
+
+/// The number of bytes of a custom error message that are kept when the
+/// `std` feature is disabled. Longer messages are truncated.
+pub const MSG_CAPACITY: usize = 64;
 
 pub type SerResult<T> = StdResult<T, SerError>;
 pub type DeResult<T> = StdResult<T, DeError>;
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum SerError {
     /// the write buffer is an invalid size
     Overflow,
-    /// enum has too many values
+    /// the enum variant index doesn't fit in the tag, see `Config::with_tag`
     EnumLarge,
+    /// error raised by a `Serialize` implementation
+    Custom(Message),
+    /// the serde type can't be encoded
+    Unsupported(Unsupported),
+    /// a string, byte slice or sequence is longer than `Options::MAX_LEN`, or a
+    /// length-prefixed frame is longer than its header can hold
+    LengthLarge,
+    /// a sequence did not report its length up front
+    LengthUnknown,
+    /// a `Usize`/`Isize` doesn't fit in the width set by `Config::with_usize`
+    IntLarge,
+    /// the `ByteSink` of a `SinkOutput` failed, its error is kept by the
+    /// output (`to_sink` returns it as `SinkError::Sink`)
+    Sink,
+    /// the writer of `to_writer` failed
+    #[cfg(feature = "std")]
+    Io(io::ErrorKind),
+    /// the `embedded_io` writer failed
+    #[cfg(feature = "embedded-io")]
+    EmbeddedIo(embedded_io::ErrorKind),
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum DeError {
     /// the data in the buffer is smaller than the type
     /// (the type is larger than the data available)
@@ -28,17 +61,284 @@ pub enum DeError {
     ExpectedBoolean,
     /// expected specific value in an Enum
     InvalidVariant,
+    /// error raised by a `Deserialize` implementation
+    Custom(Message),
+    /// the type was not the one expected by the `Deserialize` implementation
+    InvalidType,
+    /// the value was rejected by the `Deserialize` implementation
+    InvalidValue,
+    /// a sequence or tuple had the wrong number of elements
+    InvalidLength(usize),
+    /// the variant name is not known by the `Deserialize` implementation
+    UnknownVariant,
+    /// the struct field was missing
+    MissingField(&'static str),
+    /// the serde type can't be decoded
+    Unsupported(Unsupported),
+    /// a length prefix is larger than `Options::MAX_LEN`, or a value doesn't
+    /// fit in the buffer of an `embedded` reader
+    LengthLarge,
+    /// a string is not valid UTF-8
+    InvalidUtf8,
+    /// a varint is longer than 10 bytes or too large for its type
+    InvalidVarint,
+    /// a char is not a valid UTF-8 encoded scalar value
+    InvalidChar,
+    /// the framing of a message (e.g. COBS) is corrupt
+    InvalidFrame,
+    /// the CRC trailer doesn't match the message
+    ChecksumMismatch,
+    /// the length header of a frame is larger than the maximum allowed
+    FrameTooLarge,
+    /// the reader of `from_reader` failed (running out of data is
+    /// `BufferSmall`)
+    #[cfg(feature = "std")]
+    Io(io::ErrorKind),
+    /// the `embedded_io` reader failed (running out of data is `BufferSmall`)
+    #[cfg(feature = "embedded-io")]
+    EmbeddedIo(embedded_io::ErrorKind),
 }
 
-// impl SerError
+/// The serde data model types (and deserializer hints) that ubyte does not
+/// support, because they have no known maximum size or need a self-describing
+/// format. Strings, byte slices and sequences can be enabled through
+/// `Config::with_length`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unsupported {
+    Str,
+    Bytes,
+    Seq,
+    Map,
+    /// `deserialize_any`: the format is not self-describing
+    Any,
+    /// `deserialize_identifier`
+    Identifier,
+    /// `deserialize_ignored_any`
+    IgnoredAny,
+}
 
-#[cfg(feature = "std")]
-impl ::std::error::Error for SerError {
-    fn description(&self) -> &str {
-        "TODO: no description yet"
+/// A `DeError` along with where in the input it happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorContext {
+    pub error: DeError,
+    /// where the value that failed starts, i.e. the number of bytes decoded
+    /// before it
+    pub offset: usize,
+    /// the struct, field and variant names leading to the value that failed
+    #[cfg(feature = "path")]
+    pub path: Path,
+}
+
+/// The error of `to_sink`: either encoding the value failed or the
+/// `ByteSink` did, with the sink's own error.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SinkError<E> {
+    Ser(SerError),
+    Sink(E),
+}
+
+/// The maximum number of names recorded in a `Path`. Deeper names are
+/// dropped.
+#[cfg(feature = "path")]
+pub const PATH_DEPTH: usize = 8;
+
+/// A fixed-depth breadcrumb of struct, field and variant names, i.e.
+/// `Frame.reading.Reading.Gps.Gps.fix`.
+#[cfg(feature = "path")]
+#[derive(Clone, PartialEq)]
+pub struct Path {
+    segments: [&'static str; PATH_DEPTH],
+    // may be larger than PATH_DEPTH
+    depth: usize,
+}
+
+/// The message of a custom error.
+///
+/// With the `std` feature this is a `String`, otherwise the message is
+/// truncated to `MSG_CAPACITY` bytes and stored inline so no allocation is
+/// needed.
+#[derive(Clone, PartialEq)]
+pub struct Message {
+    #[cfg(feature = "std")]
+    text: String,
+    #[cfg(not(feature = "std"))]
+    bytes: [u8; MSG_CAPACITY],
+    #[cfg(not(feature = "std"))]
+    len: usize,
+}
+
+// impl Message
+
+impl Message {
+    #[cfg(feature = "std")]
+    fn empty() -> Message {
+        Message { text: String::new() }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn empty() -> Message {
+        Message {
+            bytes: [0; MSG_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Format `msg` into a new message.
+    pub fn new<T: fmt::Display>(msg: T) -> Message {
+        let mut out = Message::empty();
+        let _ = write!(out, "{}", msg);
+        out
+    }
+
+    #[cfg(feature = "std")]
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn as_str(&self) -> &str {
+        // only whole characters are ever written
+        ::core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
     }
 }
 
+impl Write for Message {
+    #[cfg(feature = "std")]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.text.push_str(s);
+        Ok(())
+    }
+
+    /// Append as much of `s` as fits, never splitting a character.
+    #[cfg(not(feature = "std"))]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut end = s.len().min(MSG_CAPACITY - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// impl ErrorContext
+
+impl fmt::Display for ErrorContext {
+    #[cfg(feature = "path")]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {} ({})", self.error, self.offset, self.path)
+    }
+
+    #[cfg(not(feature = "path"))]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.error, self.offset)
+    }
+}
+
+impl ::serde::de::StdError for ErrorContext {}
+
+// impl SinkError
+
+impl<E> From<SerError> for SinkError<E> {
+    fn from(err: SerError) -> Self {
+        SinkError::Ser(err)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for SinkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<E: fmt::Debug> ::serde::ser::StdError for SinkError<E> {}
+
+// impl Path
+
+#[cfg(feature = "path")]
+impl Path {
+    pub(crate) fn new() -> Path {
+        Path {
+            segments: [""; PATH_DEPTH],
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, segment: &'static str) {
+        if self.depth < PATH_DEPTH {
+            self.segments[self.depth] = segment;
+        }
+        self.depth += 1;
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Drop the names pushed since the path was `depth` names deep.
+    pub(crate) fn truncate(&mut self, depth: usize) {
+        self.depth = self.depth.min(depth);
+    }
+
+    /// The recorded names, outermost first.
+    pub fn segments(&self) -> &[&'static str] {
+        &self.segments[..self.depth.min(PATH_DEPTH)]
+    }
+
+    /// Whether names deeper than `PATH_DEPTH` were dropped.
+    pub fn is_truncated(&self) -> bool {
+        self.depth > PATH_DEPTH
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth == 0
+    }
+}
+
+#[cfg(feature = "path")]
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.segments().iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            f.write_str(segment)?;
+        }
+        if self.is_truncated() {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "path")]
+impl fmt::Debug for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Path({})", self)
+    }
+}
+
+// impl SerError
+
+impl ::serde::ser::StdError for SerError {}
+
 impl fmt::Display for SerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -47,19 +347,20 @@ impl fmt::Display for SerError {
 
 impl ::serde::ser::Error for SerError {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        panic!("{}", msg)
+        SerError::Custom(Message::new(msg))
     }
 }
 
-// impl DeError
-
 #[cfg(feature = "std")]
-impl ::std::error::Error for DeError {
-    fn description(&self) -> &str {
-        "TODO: no description yet"
+impl From<io::Error> for SerError {
+    fn from(err: io::Error) -> Self {
+        SerError::Io(err.kind())
     }
 }
 
+// impl DeError
+
+impl ::serde::de::StdError for DeError {}
 
 impl fmt::Display for DeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -67,8 +368,61 @@ impl fmt::Display for DeError {
     }
 }
 
+#[cfg(feature = "std")]
+impl From<io::Error> for DeError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => DeError::BufferSmall,
+            kind => DeError::Io(kind),
+        }
+    }
+}
+
 impl ::serde::de::Error for DeError {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        panic!("{}", msg)
+        DeError::Custom(Message::new(msg))
+    }
+
+    fn invalid_type(_unexp: Unexpected, _exp: &dyn Expected) -> Self {
+        DeError::InvalidType
+    }
+
+    fn invalid_value(_unexp: Unexpected, exp: &dyn Expected) -> Self {
+        if varint::is_fitting(exp) {
+            // raised by `Varint`
+            DeError::InvalidVarint
+        } else if tagged::is_listed(exp) {
+            // raised by `Tagged`
+            DeError::InvalidVariant
+        } else {
+            DeError::InvalidValue
+        }
+    }
+
+    fn invalid_length(len: usize, _exp: &dyn Expected) -> Self {
+        DeError::InvalidLength(len)
+    }
+
+    fn unknown_variant(_variant: &str, _expected: &'static [&'static str]) -> Self {
+        DeError::UnknownVariant
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        DeError::MissingField(field)
+    }
+}
+
+#[test]
+fn test_message() {
+    let msg = Message::new(format_args!("value {} out of range", 300));
+    assert_eq!(msg.as_str(), "value 300 out of range");
+    assert_eq!(msg, Message::new("value 300 out of range"));
+
+    // messages longer than the inline buffer are cut on a char boundary
+    let long = Message::new("é".repeat(MSG_CAPACITY));
+    if cfg!(feature = "std") {
+        assert_eq!(long.as_str().len(), MSG_CAPACITY * 2);
+    } else {
+        assert_eq!(long.as_str(), "é".repeat(MSG_CAPACITY / 2));
     }
 }
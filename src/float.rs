@@ -0,0 +1,113 @@
+//! half precision float module
+//!
+//! `F16` stores a float in 2 bytes, for sensor values that don't need the
+//! range or precision of an `f32` (requires the `half` feature).
+//!
+//! ```
+//! use ubyte::F16;
+//!
+//! let mut buffer = [0u8; 2];
+//! ubyte::to_bytes(&mut buffer, &F16::from_f32(-1.5)).unwrap();
+//! assert_eq!(buffer, [0xBE, 0x00]);
+//! assert_eq!(ubyte::from_bytes::<F16>(&buffer).unwrap().to_f32(), -1.5);
+//! ```
+
+use core::fmt;
+
+use half::f16;
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::{Serialize, Serializer};
+
+use crate::max_size::MaxSizeWith;
+use crate::options::Options;
+
+/// The newtype name `F16` passes to the serializer and deserializer, which
+/// then keep its bits fixed width like the other floats.
+#[doc(hidden)]
+pub const NAME: &str = "ubyte::F16";
+
+/// A half precision float, encoded as the `u16` holding its bits. Like `f32`
+/// and `f64` it takes up 2 bytes even with `VarintEncoding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct F16(pub f16);
+
+impl F16 {
+    /// Convert `v`, rounding to the nearest half precision value.
+    pub fn from_f32(v: f32) -> F16 {
+        F16(f16::from_f32(v))
+    }
+
+    /// The value as an `f32`, which is always exact.
+    pub fn to_f32(self) -> f32 {
+        self.0.to_f32()
+    }
+}
+
+impl From<f16> for F16 {
+    fn from(v: f16) -> F16 {
+        F16(v)
+    }
+}
+
+impl From<F16> for f32 {
+    fn from(v: F16) -> f32 {
+        v.to_f32()
+    }
+}
+
+impl Serialize for F16 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(NAME, &self.0.to_bits())
+    }
+}
+
+struct F16Visitor;
+
+impl<'de> Visitor<'de> for F16Visitor {
+    type Value = F16;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the bits of a half precision float")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<F16, D::Error> {
+        Ok(F16(f16::from_bits(u16::deserialize(deserializer)?)))
+    }
+}
+
+impl<'de> Deserialize<'de> for F16 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(NAME, F16Visitor)
+    }
+}
+
+impl<O: Options> MaxSizeWith<O> for F16 {
+    const MAX_SIZE: usize = 2;
+}
+
+#[test]
+fn test_f16() {
+    use crate::de::{from_bytes, from_bytes_with};
+    use crate::max_size::MaxSize;
+    use crate::options::Config;
+    use crate::ser::{to_bytes, to_bytes_with};
+
+    let mut buffer = [0u8; 4];
+    let value = (F16::from_f32(1.0), F16(f16::INFINITY));
+    assert_eq!(to_bytes(&mut buffer, &value).unwrap(), <(F16, F16) as MaxSize>::MAX_SIZE);
+    assert_eq!(buffer, [0x3C, 0x00, 0x7C, 0x00]);
+    assert_eq!(from_bytes::<(F16, F16)>(&buffer).unwrap(), value);
+
+    let options = Config::new().with_little_endian();
+    to_bytes_with(options, &mut buffer[..2], &F16::from_f32(0.1)).unwrap();
+    assert_eq!(&buffer[..2], &[0x66, 0x2E]);
+    let v: F16 = from_bytes_with(options, &buffer[..2]).unwrap();
+    assert!((v.to_f32() - 0.1).abs() < 1e-3);
+
+    // raw bits in the byte order of the options, not a varint
+    let options = options.with_varint_encoding();
+    let value = (F16::from_f32(-2.0), 300u16);
+    assert_eq!(to_bytes_with(options, &mut buffer, &value).unwrap(), 4);
+    assert_eq!(buffer, [0x00, 0xC0, 0xAC, 0x02]);
+    assert_eq!(from_bytes_with::<_, (F16, u16)>(options, &buffer).unwrap(), value);
+}
@@ -0,0 +1,312 @@
+//! COBS (Consistent Overhead Byte Stuffing) framing
+//!
+//! The payload is encoded so it contains no zero bytes and is followed by a
+//! single zero byte, which marks the end of the frame. A payload of `n` bytes
+//! takes up at most `max_frame_size(n)` bytes:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # #[macro_use] extern crate ubyte_derive;
+//! # extern crate ubyte;
+//! use ubyte::MaxSize;
+//! use ubyte::framing::cobs;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize, MaxSize)]
+//! struct Reading {
+//!     channel: u8,
+//!     value: i32,
+//! }
+//!
+//! # fn main() {
+//! let mut frame = [0u8; cobs::max_frame_size(Reading::MAX_SIZE)];
+//! let reading = Reading { channel: 3, value: 40 };
+//! let len = cobs::to_bytes(&mut frame, &reading).unwrap();
+//! assert_eq!(&frame[..len], &[2, 3, 1, 1, 2, 40, 0]);
+//! assert_eq!(cobs::from_bytes::<Reading>(&mut frame[..len]).unwrap(), reading);
+//! # }
+//! ```
+
+use crate::dev_prefix::*;
+
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+use crate::options::{DefaultOptions, Options};
+use crate::ser;
+
+/// The frame delimiter.
+pub const DELIMITER: u8 = 0;
+
+/// The largest frame, including the delimiter, a payload of `payload` bytes
+/// can be encoded to.
+pub const fn max_frame_size(payload: usize) -> usize {
+    // a code byte for every 254 bytes (and at least one), plus the delimiter
+    payload + payload / 254 + 2
+}
+
+/// serialize the value into `buffer` as a COBS frame, including the
+/// delimiter, and return the length of the frame.
+pub fn to_bytes<T>(buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where T: Serialize + ?Sized
+{
+    to_bytes_with(DefaultOptions::new(), buffer, value)
+}
+
+/// Same as `to_bytes` but using the encoding `options`.
+pub fn to_bytes_with<O, T>(options: O, buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where O: Options,
+          T: Serialize + ?Sized
+{
+    let len = ser::to_bytes_with(options, buffer, value)?;
+    encode_in_place(buffer, len)
+}
+
+/// Encode the payload `buffer[..len]` in place as a COBS frame, including the
+/// delimiter, and return the length of the frame.
+///
+/// `buffer` must have room for `max_frame_size(len)` bytes.
+pub fn encode_in_place(buffer: &mut [u8], len: usize) -> SerResult<usize> {
+    if max_frame_size(len) > buffer.len() {
+        return Err(SerError::Overflow);
+    }
+    // move the payload up by the number of code bytes so it can be encoded in
+    // place without overwriting bytes that haven't been read yet
+    let start = len / 254 + 1;
+    buffer.copy_within(..len, start);
+    Ok(stuff(buffer, start, start + len))
+}
+
+/// Encode `buffer[start..end]` to the start of `buffer`, returning the length
+/// of the frame.
+fn stuff(buffer: &mut [u8], start: usize, end: usize) -> usize {
+    let mut code_pos = 0;
+    let mut code = 1u8;
+    let mut dst = 1;
+    for src in start..end {
+        let byte = buffer[src];
+        if byte != DELIMITER {
+            buffer[dst] = byte;
+            dst += 1;
+            code += 1;
+        }
+        if byte == DELIMITER || code == 0xFF {
+            buffer[code_pos] = code;
+            code_pos = dst;
+            dst += 1;
+            code = 1;
+        }
+    }
+    buffer[code_pos] = code;
+    buffer[dst] = DELIMITER;
+    dst + 1
+}
+
+/// Decode a COBS frame in place, returning the length of the payload at the
+/// start of `frame`.
+///
+/// A trailing delimiter is ignored, so a frame of only the delimiter is
+/// empty, as in `slip::decode_in_place`.
+pub fn decode_in_place(frame: &mut [u8]) -> DeResult<usize> {
+    let end = match frame.split_last() {
+        Some((&DELIMITER, _)) => frame.len() - 1,
+        _ => frame.len(),
+    };
+    if end == 0 {
+        return Ok(0);
+    }
+    let mut src = 0;
+    let mut dst = 0;
+    while src < end {
+        let code = frame[src] as usize;
+        let block_end = src + code;
+        if code == 0 || block_end > end {
+            return Err(DeError::InvalidFrame);
+        }
+        for i in src + 1..block_end {
+            if frame[i] == DELIMITER {
+                return Err(DeError::InvalidFrame);
+            }
+            frame[dst] = frame[i];
+            dst += 1;
+        }
+        src = block_end;
+        // every block but the last and full ones ends with a zero
+        if code < 0xFF && src < end {
+            frame[dst] = 0;
+            dst += 1;
+        }
+    }
+    Ok(dst)
+}
+
+/// Decode a COBS frame in place and deserialize the payload.
+pub fn from_bytes<'de, T>(frame: &'de mut [u8]) -> DeResult<T>
+    where T: Deserialize<'de>
+{
+    from_bytes_with(DefaultOptions::new(), frame)
+}
+
+/// Same as `from_bytes` but using the encoding `options`.
+pub fn from_bytes_with<'de, O, T>(options: O, frame: &'de mut [u8]) -> DeResult<T>
+    where O: Options,
+          T: Deserialize<'de>
+{
+    let len = decode_in_place(frame)?;
+    let frame: &'de [u8] = frame;
+    de::from_bytes_with(options, &frame[..len])
+}
+
+/// Collects a COBS frame a byte at a time into a fixed buffer, e.g. from a
+/// UART interrupt.
+///
+/// `N` must be able to hold a whole frame without its delimiter, i.e.
+/// `max_frame_size(T::MAX_SIZE) - 1`.
+pub struct Accumulator<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    // the current frame didn't fit and is being skipped
+    overflow: bool,
+}
+
+impl<const N: usize> Accumulator<N> {
+    /// Create an empty accumulator.
+    pub fn new() -> Self {
+        Accumulator { buffer: [0; N], len: 0, overflow: false }
+    }
+
+    /// Add the next byte received.
+    ///
+    /// Returns the decoded payload once the delimiter of a frame arrives, or
+    /// an error if the frame was corrupt or didn't fit (`BufferSmall`). Empty
+    /// frames are skipped.
+    pub fn push(&mut self, byte: u8) -> Option<DeResult<&[u8]>> {
+        if byte != DELIMITER {
+            if self.len < N {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflow = true;
+            }
+            return None;
+        }
+        let len = mem::replace(&mut self.len, 0);
+        if mem::replace(&mut self.overflow, false) {
+            return Some(Err(DeError::BufferSmall));
+        }
+        if len == 0 {
+            return None;
+        }
+        let frame = &mut self.buffer[..len];
+        Some(decode_in_place(frame).map(move |len| &frame[..len]))
+    }
+
+    /// Drop the bytes of the current frame.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+    }
+}
+
+impl<const N: usize> Default for Accumulator<N> {
+    fn default() -> Self {
+        Accumulator::new()
+    }
+}
+
+#[test]
+fn test_cobs_vectors() {
+    fn check(payload: &[u8], frame: &[u8]) {
+        let mut buffer = [0u8; 600];
+        buffer[..payload.len()].copy_from_slice(payload);
+        let len = encode_in_place(&mut buffer, payload.len()).unwrap();
+        assert_eq!(&buffer[..len], frame);
+        assert!(len <= max_frame_size(payload.len()));
+        assert_eq!(decode_in_place(&mut buffer[..len]).unwrap(), payload.len());
+        assert_eq!(&buffer[..payload.len()], payload);
+    }
+
+    check(&[], &[1, 0]);
+    check(&[0], &[1, 1, 0]);
+    check(&[0, 0], &[1, 1, 1, 0]);
+    check(&[0x11, 0x22, 0, 0x33], &[3, 0x11, 0x22, 2, 0x33, 0]);
+    check(&[0x11, 0x22, 0x33, 0x44], &[5, 0x11, 0x22, 0x33, 0x44, 0]);
+    check(&[0x11, 0, 0, 0], &[2, 0x11, 1, 1, 1, 0]);
+
+    let long: Vec<u8> = (1..=254).collect();
+    let mut frame = vec![0xFF];
+    frame.extend_from_slice(&long);
+    frame.extend_from_slice(&[1, 0]);
+    check(&long, &frame);
+
+    let long: Vec<u8> = (0..=255).map(|b: u16| b as u8).collect();
+    let mut frame = vec![1, 0xFF];
+    frame.extend_from_slice(&long[1..255]);
+    frame.extend_from_slice(&[2, 0xFF, 0]);
+    check(&long, &frame);
+
+    // empty frames, like in SLIP
+    assert_eq!(decode_in_place(&mut []).unwrap(), 0);
+    assert_eq!(decode_in_place(&mut [0]).unwrap(), 0);
+    assert_eq!(decode_in_place(&mut [3, 1, 0]).unwrap_err(), DeError::InvalidFrame);
+    assert_eq!(decode_in_place(&mut [3, 1]).unwrap_err(), DeError::InvalidFrame);
+    assert_eq!(decode_in_place(&mut [1, 0, 1]).unwrap_err(), DeError::InvalidFrame);
+}
+
+#[test]
+fn test_cobs_value() {
+    use crate::max_size::MaxSize;
+    use crate::options::{Config, U16};
+
+    let mut frame = [0u8; max_frame_size(<(u8, u32) as MaxSize>::MAX_SIZE)];
+    let len = to_bytes(&mut frame, &(0u8, 0x01000200u32)).unwrap();
+    assert_eq!(&frame[..len], &[1, 2, 1, 2, 2, 1, 0]);
+    assert_eq!(from_bytes::<(u8, u32)>(&mut frame[..len]).unwrap(), (0, 0x01000200));
+
+    // the payload fits but the frame doesn't
+    assert_eq!(to_bytes(&mut frame[..6], &(0u8, 1u32)).unwrap_err(), SerError::Overflow);
+
+    let options = Config::new().with_length::<U16, 1000>();
+    let payload: Vec<u8> = (0..700).map(|i| (i % 7) as u8).collect();
+    let mut frame = [0u8; max_frame_size(2 + 700)];
+    let len = to_bytes_with(options, &mut frame, &payload).unwrap();
+    assert!(!frame[..len - 1].contains(&DELIMITER));
+    assert_eq!(frame[len - 1], DELIMITER);
+    assert_eq!(from_bytes_with::<_, Vec<u8>>(options, &mut frame[..len]).unwrap(), payload);
+
+    let payload = vec![0x55; 700];
+    let len = to_bytes_with(options, &mut frame, &payload).unwrap();
+    assert_eq!(len, max_frame_size(2 + 700));
+    assert_eq!(from_bytes_with::<_, Vec<u8>>(options, &mut frame[..len]).unwrap(), payload);
+}
+
+#[test]
+fn test_cobs_accumulator() {
+    let mut frames = [0u8; 32];
+    let mut len = to_bytes(&mut frames, &(1u8, 0u16)).unwrap();
+    len += to_bytes(&mut frames[len..], &(2u8, 0x300u16)).unwrap();
+
+    let mut accumulator = Accumulator::<8>::new();
+    // garbage before the first frame and repeated delimiters
+    assert!(accumulator.push(0).is_none());
+    assert!(accumulator.push(0).is_none());
+    let mut values = Vec::new();
+    for &byte in &frames[..len] {
+        if let Some(payload) = accumulator.push(byte) {
+            values.push(crate::from_bytes::<(u8, u16)>(payload.unwrap()).unwrap());
+        }
+    }
+    assert_eq!(values, vec![(1, 0), (2, 0x300)]);
+
+    for _ in 0..9 {
+        assert!(accumulator.push(0x11).is_none());
+    }
+    assert_eq!(accumulator.push(0), Some(Err(DeError::BufferSmall)));
+    assert_eq!(accumulator.push(3), None);
+    assert_eq!(accumulator.push(1), None);
+    assert_eq!(accumulator.push(0), Some(Err(DeError::InvalidFrame)));
+    accumulator.push(0x11);
+    accumulator.reset();
+    assert_eq!(accumulator.push(2), None);
+    assert_eq!(accumulator.push(7), None);
+    assert_eq!(accumulator.push(0), Some(Ok(&[7][..])));
+}
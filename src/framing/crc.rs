@@ -0,0 +1,232 @@
+//! CRC integrity trailer
+//!
+//! Appends a checksum over the bytes produced by `to_bytes`, in the byte order
+//! of the options, and verifies it before decoding. The algorithm is picked
+//! with a type, `Crc16` or `Crc32`. The trailer adds `C::SIZE` bytes to the
+//! encoded size:
+//!
+//! ```
+//! # extern crate ubyte;
+//! use ubyte::framing::crc::{self, Crc, Crc16};
+//! use ubyte::DeError;
+//!
+//! # fn main() {
+//! let mut buffer = [0u8; 4 + Crc16::SIZE];
+//! let len = crc::to_bytes::<Crc16, _>(&mut buffer, &0x01020304u32).unwrap();
+//! assert_eq!(&buffer[..len], &[1, 2, 3, 4, 0x89, 0xC3]);
+//! assert_eq!(crc::from_bytes::<Crc16, u32>(&buffer[..len]).unwrap(), 0x01020304);
+//!
+//! buffer[0] ^= 0x10;
+//! assert_eq!(crc::from_bytes::<Crc16, u32>(&buffer[..len]).unwrap_err(),
+//!            DeError::ChecksumMismatch);
+//! # }
+//! ```
+
+use crate::dev_prefix::*;
+
+use byteorder::ByteOrder;
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+use crate::options::{DefaultOptions, Options};
+use crate::ser;
+
+/// A CRC algorithm.
+pub trait Crc {
+    /// The number of bytes of the trailer.
+    const SIZE: usize;
+
+    /// The CRC of `bytes`.
+    fn checksum(bytes: &[u8]) -> u32;
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF, not
+/// reflected. The check value of `b"123456789"` is 0x29B1.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc16;
+
+/// CRC-32 (as used by Ethernet and zip): polynomial 0x04C11DB7, reflected,
+/// initial value and final xor 0xFFFFFFFF. The check value of `b"123456789"`
+/// is 0xCBF43926.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32;
+
+const fn crc16_table(poly: u16) -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ poly } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// `poly` is the reflected polynomial
+const fn crc32_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC16_TABLE: [u16; 256] = crc16_table(0x1021);
+static CRC32_TABLE: [u32; 256] = crc32_table(0xEDB8_8320);
+
+impl Crc for Crc16 {
+    const SIZE: usize = 2;
+
+    fn checksum(bytes: &[u8]) -> u32 {
+        let mut crc = 0xFFFFu16;
+        for &byte in bytes {
+            crc = (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ byte) as usize];
+        }
+        crc as u32
+    }
+}
+
+impl Crc for Crc32 {
+    const SIZE: usize = 4;
+
+    fn checksum(bytes: &[u8]) -> u32 {
+        let mut crc = 0xFFFF_FFFFu32;
+        for &byte in bytes {
+            crc = (crc >> 8) ^ CRC32_TABLE[(crc as u8 ^ byte) as usize];
+        }
+        !crc
+    }
+}
+
+/// Append the CRC of `buffer[..len]` after it and return the new length.
+pub fn append<C: Crc>(buffer: &mut [u8], len: usize) -> SerResult<usize> {
+    append_with::<C, _>(DefaultOptions::new(), buffer, len)
+}
+
+/// Same as `append` but writing the CRC in the byte order of `options`.
+pub fn append_with<C, O>(_options: O, buffer: &mut [u8], len: usize) -> SerResult<usize>
+    where C: Crc,
+          O: Options
+{
+    let end = len + C::SIZE;
+    if end > buffer.len() {
+        return Err(SerError::Overflow);
+    }
+    let crc = C::checksum(&buffer[..len]);
+    O::Endian::write_uint(&mut buffer[len..end], crc as u64, C::SIZE);
+    Ok(end)
+}
+
+/// Check the CRC at the end of `bytes` and return the bytes before it.
+pub fn verify<C: Crc>(bytes: &[u8]) -> DeResult<&[u8]> {
+    verify_with::<C, _>(DefaultOptions::new(), bytes)
+}
+
+/// Same as `verify` but reading the CRC in the byte order of `options`.
+pub fn verify_with<C, O>(_options: O, bytes: &[u8]) -> DeResult<&[u8]>
+    where C: Crc,
+          O: Options
+{
+    if bytes.len() < C::SIZE {
+        return Err(DeError::BufferSmall);
+    }
+    let (payload, trailer) = bytes.split_at(bytes.len() - C::SIZE);
+    if O::Endian::read_uint(trailer, C::SIZE) as u32 == C::checksum(payload) {
+        Ok(payload)
+    } else {
+        Err(DeError::ChecksumMismatch)
+    }
+}
+
+/// serialize the value in the buffer followed by its CRC and return the
+/// length of the buffer used.
+pub fn to_bytes<C, T>(buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where C: Crc,
+          T: Serialize + ?Sized
+{
+    to_bytes_with::<C, _, _>(DefaultOptions::new(), buffer, value)
+}
+
+/// Same as `to_bytes` but using the encoding `options`.
+pub fn to_bytes_with<C, O, T>(options: O, buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where C: Crc,
+          O: Options,
+          T: Serialize + ?Sized
+{
+    let len = ser::to_bytes_with(options, buffer, value)?;
+    append_with::<C, _>(options, buffer, len)
+}
+
+/// Check the CRC at the end of `bytes` and deserialize the value before it.
+pub fn from_bytes<'de, C, T>(bytes: &'de [u8]) -> DeResult<T>
+    where C: Crc,
+          T: Deserialize<'de>
+{
+    from_bytes_with::<C, _, _>(DefaultOptions::new(), bytes)
+}
+
+/// Same as `from_bytes` but using the encoding `options`.
+pub fn from_bytes_with<'de, C, O, T>(options: O, bytes: &'de [u8]) -> DeResult<T>
+    where C: Crc,
+          O: Options,
+          T: Deserialize<'de>
+{
+    let payload = verify_with::<C, _>(options, bytes)?;
+    de::from_bytes_with(options, payload)
+}
+
+#[test]
+fn test_crc_check_values() {
+    assert_eq!(Crc16::checksum(b"123456789"), 0x29B1);
+    assert_eq!(Crc32::checksum(b"123456789"), 0xCBF4_3926);
+    assert_eq!(Crc16::checksum(b""), 0xFFFF);
+    assert_eq!(Crc32::checksum(b""), 0);
+}
+
+#[test]
+fn test_crc_value() {
+    use crate::options::Config;
+
+    let mut buffer = [0u8; 16];
+    let len = to_bytes::<Crc32, _>(&mut buffer, &(7u8, -1i16)).unwrap();
+    assert_eq!(len, 3 + 4);
+    let crc = Crc32::checksum(&[7, 0xFF, 0xFF]);
+    assert_eq!(&buffer[3..len], &[(crc >> 24) as u8, (crc >> 16) as u8, (crc >> 8) as u8,
+                                  crc as u8]);
+    assert_eq!(from_bytes::<Crc32, (u8, i16)>(&buffer[..len]).unwrap(), (7, -1));
+    assert_eq!(verify::<Crc32>(&buffer[..len]).unwrap(), &[7, 0xFF, 0xFF]);
+
+    // every single bit flip is caught
+    for i in 0..len * 8 {
+        buffer[i / 8] ^= 1 << (i % 8);
+        assert_eq!(from_bytes::<Crc32, (u8, i16)>(&buffer[..len]).unwrap_err(),
+                   DeError::ChecksumMismatch);
+        buffer[i / 8] ^= 1 << (i % 8);
+    }
+
+    let options = Config::new().with_little_endian();
+    let len = to_bytes_with::<Crc16, _, _>(options, &mut buffer, &0x0102u16).unwrap();
+    let crc = Crc16::checksum(&[2, 1]);
+    assert_eq!(&buffer[..len], &[2, 1, crc as u8, (crc >> 8) as u8]);
+    assert_eq!(from_bytes_with::<Crc16, _, u16>(options, &buffer[..len]).unwrap(), 0x0102);
+    assert_eq!(from_bytes::<Crc16, u16>(&buffer[..len]).unwrap_err(),
+               DeError::ChecksumMismatch);
+
+    // no room for the trailer, or no trailer at all
+    assert_eq!(to_bytes::<Crc16, _>(&mut buffer[..3], &0u16).unwrap_err(), SerError::Overflow);
+    assert_eq!(from_bytes::<Crc32, ()>(&[1, 2, 3]).unwrap_err(), DeError::BufferSmall);
+    assert_eq!(append::<Crc16>(&mut buffer[..3], 2).unwrap_err(), SerError::Overflow);
+}
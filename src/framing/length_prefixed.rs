@@ -0,0 +1,285 @@
+//! Length-prefixed framing for stream transports
+//!
+//! Each frame is a fixed width length header, `U8`, `U16` or `U32`, written
+//! in the byte order of the options, followed by that many bytes of payload.
+//! The decoder checks the header against a maximum before waiting for the
+//! payload, so a corrupt or hostile header is rejected with
+//! `DeError::FrameTooLarge`:
+//!
+//! ```
+//! # extern crate ubyte;
+//! use ubyte::framing::length_prefixed;
+//! use ubyte::options::U16;
+//! use ubyte::DeError;
+//!
+//! # fn main() {
+//! let mut buffer = [0u8; length_prefixed::max_frame_size::<U16>(4) * 2];
+//! let mut len = length_prefixed::to_bytes::<U16, _>(&mut buffer, &0x01020304u32).unwrap();
+//! len += length_prefixed::to_bytes::<U16, _>(&mut buffer[len..], &7u8).unwrap();
+//! assert_eq!(&buffer[..len], &[0, 4, 1, 2, 3, 4, 0, 1, 7]);
+//!
+//! let (first, rest) = length_prefixed::take_from_bytes::<U16, u32>(&buffer[..len], 16).unwrap();
+//! let (second, rest) = length_prefixed::take_from_bytes::<U16, u8>(rest, 16).unwrap();
+//! assert_eq!((first, second, rest.len()), (0x01020304, 7, 0));
+//!
+//! assert_eq!(length_prefixed::take_from_bytes::<U16, u32>(&buffer[..len], 3).unwrap_err(),
+//!            DeError::FrameTooLarge);
+//! # }
+//! ```
+//!
+//! `Disabled` has no header to write, so using it fails to compile:
+//!
+//! ```compile_fail
+//! # extern crate ubyte;
+//! use ubyte::framing::length_prefixed;
+//! use ubyte::options::Disabled;
+//!
+//! # fn main() {
+//! let mut buffer = [0u8; 8];
+//! length_prefixed::to_bytes::<Disabled, _>(&mut buffer, &0x01020304u32).unwrap();
+//! # }
+//! ```
+
+use crate::dev_prefix::*;
+
+use core::marker::PhantomData;
+
+use byteorder::ByteOrder;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::de;
+use crate::options::{DefaultOptions, LengthPrefix, Options};
+use crate::ser;
+
+/// The length header `H`, only the widths of `LengthPrefix` that are enabled.
+pub(crate) struct Header<H>(PhantomData<H>);
+
+impl<H: LengthPrefix> Header<H> {
+    /// The size of the header, failing to compile for `Disabled`.
+    pub(crate) const SIZE: usize = {
+        assert!(H::ENABLED, "a frame header needs `U8`, `U16` or `U32`");
+        H::MAX_SIZE
+    };
+}
+
+/// The size of a frame with a payload of `payload` bytes.
+pub const fn max_frame_size<H: LengthPrefix>(payload: usize) -> usize {
+    Header::<H>::SIZE + payload
+}
+
+/// serialize the value into `buffer` after its length header and return the
+/// length of the frame.
+///
+/// A payload longer than the header can hold is `SerError::LengthLarge`.
+pub fn to_bytes<H, T>(buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where H: LengthPrefix,
+          T: Serialize + ?Sized
+{
+    to_bytes_with::<H, _, _>(DefaultOptions::new(), buffer, value)
+}
+
+/// Same as `to_bytes` but using the encoding `options`.
+pub fn to_bytes_with<H, O, T>(options: O, buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where H: LengthPrefix,
+          O: Options,
+          T: Serialize + ?Sized
+{
+    if buffer.len() < Header::<H>::SIZE {
+        return Err(SerError::Overflow);
+    }
+    let (header, payload) = buffer.split_at_mut(Header::<H>::SIZE);
+    let len = ser::to_bytes_with(options, payload, value)?;
+    write_header::<H, O>(header, len)?;
+    Ok(Header::<H>::SIZE + len)
+}
+
+pub(crate) fn write_header<H, O>(header: &mut [u8], len: usize) -> SerResult<()>
+    where H: LengthPrefix,
+          O: Options
+{
+    if len > H::MAX {
+        return Err(SerError::LengthLarge);
+    }
+    O::Endian::write_uint(header, len as u64, Header::<H>::SIZE);
+    Ok(())
+}
+
+/// Read the length header at the start of `bytes` and return the length of
+/// the payload that follows it.
+///
+/// Returns `BufferSmall` if the header is incomplete and `FrameTooLarge` if
+/// the length is above `max_len`.
+pub fn payload_len<H: LengthPrefix>(bytes: &[u8], max_len: usize) -> DeResult<usize> {
+    payload_len_with::<H, _>(DefaultOptions::new(), bytes, max_len)
+}
+
+/// Same as `payload_len` but reading the header in the byte order of
+/// `options`.
+pub fn payload_len_with<H, O>(_options: O, bytes: &[u8], max_len: usize) -> DeResult<usize>
+    where H: LengthPrefix,
+          O: Options
+{
+    if bytes.len() < Header::<H>::SIZE {
+        return Err(DeError::BufferSmall);
+    }
+    let len = O::Endian::read_uint(&bytes[..Header::<H>::SIZE], Header::<H>::SIZE) as usize;
+    if len > max_len {
+        return Err(DeError::FrameTooLarge);
+    }
+    Ok(len)
+}
+
+/// Deserialize the frame at the start of `bytes` and return the value along
+/// with the bytes that follow the frame.
+///
+/// The payload must be no longer than `max_len` and decode to exactly one
+/// `T`.
+pub fn take_from_bytes<'de, H, T>(bytes: &'de [u8], max_len: usize) -> DeResult<(T, &'de [u8])>
+    where H: LengthPrefix,
+          T: Deserialize<'de>
+{
+    take_from_bytes_with::<H, _, _>(DefaultOptions::new(), bytes, max_len)
+}
+
+/// Same as `take_from_bytes` but using the encoding `options`.
+pub fn take_from_bytes_with<'de, H, O, T>(options: O, bytes: &'de [u8], max_len: usize)
+    -> DeResult<(T, &'de [u8])>
+    where H: LengthPrefix,
+          O: Options,
+          T: Deserialize<'de>
+{
+    let len = payload_len_with::<H, _>(options, bytes, max_len)?;
+    let bytes = &bytes[Header::<H>::SIZE..];
+    if bytes.len() < len {
+        return Err(DeError::BufferSmall);
+    }
+    let (payload, rest) = bytes.split_at(len);
+    Ok((de::from_bytes_with(options, payload)?, rest))
+}
+
+/// serialize the value to `writer` as a frame and return the number of bytes
+/// written.
+///
+/// The frame is assembled in memory first, so the header and payload go out
+/// in a single write.
+#[cfg(feature = "std")]
+pub fn to_writer<H, W, T>(writer: W, value: &T) -> SerResult<usize>
+    where H: LengthPrefix,
+          W: io::Write,
+          T: Serialize + ?Sized
+{
+    to_writer_with::<H, _, _, _>(DefaultOptions::new(), writer, value)
+}
+
+/// Same as `to_writer` but using the encoding `options`.
+#[cfg(feature = "std")]
+pub fn to_writer_with<H, O, W, T>(options: O, mut writer: W, value: &T) -> SerResult<usize>
+    where H: LengthPrefix,
+          O: Options,
+          W: io::Write,
+          T: Serialize + ?Sized
+{
+    let mut serializer = ser::Serializer::with_output(options, vec![0; Header::<H>::SIZE]);
+    serializer.write(value)?;
+    let mut frame = serializer.into_output();
+    let len = frame.len() - Header::<H>::SIZE;
+    write_header::<H, O>(&mut frame[..Header::<H>::SIZE], len)?;
+    writer.write_all(&frame)?;
+    Ok(frame.len())
+}
+
+/// Read a frame from `reader` and deserialize its payload.
+///
+/// The header is checked against `max_len` before the payload is read, so no
+/// more than `max_len` bytes are ever buffered.
+#[cfg(feature = "std")]
+pub fn from_reader<H, R, T>(reader: R, max_len: usize) -> DeResult<T>
+    where H: LengthPrefix,
+          R: io::Read,
+          T: DeserializeOwned
+{
+    from_reader_with::<H, _, _, _>(DefaultOptions::new(), reader, max_len)
+}
+
+/// Same as `from_reader` but using the encoding `options`.
+#[cfg(feature = "std")]
+pub fn from_reader_with<H, O, R, T>(options: O, mut reader: R, max_len: usize) -> DeResult<T>
+    where H: LengthPrefix,
+          O: Options,
+          R: io::Read,
+          T: DeserializeOwned
+{
+    let mut header = [0u8; 4];
+    let header = &mut header[..Header::<H>::SIZE];
+    reader.read_exact(header)?;
+    let len = payload_len_with::<H, _>(options, header, max_len)?;
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    de::from_bytes_with(options, &payload)
+}
+
+#[test]
+fn test_length_prefixed_bytes() {
+    use crate::options::{Config, U32, U8};
+
+    let mut buffer = [0u8; 16];
+    let options = Config::new().with_little_endian();
+    let len = to_bytes_with::<U32, _, _>(options, &mut buffer, &(1u8, 2u16)).unwrap();
+    assert_eq!(&buffer[..len], &[3, 0, 0, 0, 1, 2, 0]);
+    assert_eq!(payload_len_with::<U32, _>(options, &buffer, 3).unwrap(), 3);
+    assert_eq!(payload_len_with::<U32, _>(options, &buffer[..3], 3).unwrap_err(),
+               DeError::BufferSmall);
+    let (value, rest) = take_from_bytes_with::<U32, _, (u8, u16)>(options, &buffer[..len + 2], 8)
+        .unwrap();
+    assert_eq!((value, rest), ((1, 2), &[0u8, 0][..]));
+
+    // a truncated payload, a payload the value doesn't match and a header
+    // over the limit
+    assert_eq!(take_from_bytes_with::<U32, _, (u8, u16)>(options, &buffer[..len - 1], 8)
+                   .unwrap_err(),
+               DeError::BufferSmall);
+    assert_eq!(take_from_bytes_with::<U32, _, u16>(options, &buffer[..len], 8).unwrap_err(),
+               DeError::BufferLarge);
+    assert_eq!(take_from_bytes_with::<U32, _, (u8, u16)>(options, &buffer[..len], 2)
+                   .unwrap_err(),
+               DeError::FrameTooLarge);
+    let junk = [0xFF, 0xFF, 0xFF, 0x7F];
+    assert_eq!(take_from_bytes::<U32, u8>(&junk, 1024).unwrap_err(), DeError::FrameTooLarge);
+
+    // the payload doesn't fit in the buffer or the header
+    assert_eq!(to_bytes::<U8, _>(&mut buffer[..4], &0u32).unwrap_err(), SerError::Overflow);
+    assert_eq!(to_bytes::<U8, _>(&mut [], &()).unwrap_err(), SerError::Overflow);
+    assert_eq!(to_bytes::<U8, _>(&mut [0u8; 300], &[[0u8; 32]; 8]).unwrap_err(),
+               SerError::LengthLarge);
+    assert_eq!(to_bytes::<U8, _>(&mut buffer, &()).unwrap(), 1);
+    assert_eq!(take_from_bytes::<U8, ()>(&buffer[..1], 0).unwrap(), ((), &[][..]));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_length_prefixed_io() {
+    use crate::options::{U16, U8};
+
+    let mut stream = Vec::new();
+    assert_eq!(to_writer::<U16, _, _>(&mut stream, &0x0102u16).unwrap(), 4);
+    assert_eq!(to_writer::<U16, _, _>(&mut stream, &[9u8; 3]).unwrap(), 5);
+    assert_eq!(&stream[..], &[0, 2, 1, 2, 0, 3, 9, 9, 9]);
+    assert_eq!(to_writer::<U8, _, _>(&mut stream, &[[0u8; 32]; 8]).unwrap_err(),
+               SerError::LengthLarge);
+    assert_eq!(stream.len(), 9);
+
+    let mut reader = &stream[..];
+    assert_eq!(from_reader::<U16, _, u16>(&mut reader, 8).unwrap(), 0x0102);
+    assert_eq!(from_reader::<U16, _, [u8; 3]>(&mut reader, 8).unwrap(), [9; 3]);
+    assert_eq!(from_reader::<U16, _, u8>(&mut reader, 8).unwrap_err(), DeError::BufferSmall);
+
+    // the payload isn't waited for once the header is over the limit
+    let mut reader = &stream[..3];
+    assert_eq!(from_reader::<U16, _, u16>(&mut reader, 1).unwrap_err(), DeError::FrameTooLarge);
+    assert_eq!(reader, &[1][..]);
+}
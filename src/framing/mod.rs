@@ -0,0 +1,16 @@
+//! framing module
+//!
+//! The encoding itself has no delimiters, so a receiver can't tell where one
+//! message ends and the next begins. `cobs` and `slip` wrap `to_bytes` output
+//! in self-delimiting frames for serial links, `length_prefixed` puts a length
+//! header in front of it for stream transports, and `crc` adds a checksum so
+//! corrupted messages are rejected. `FrameReceiver` combines both to decode
+//! values from a byte stream as it arrives.
+
+pub mod cobs;
+pub mod crc;
+pub mod length_prefixed;
+mod receiver;
+pub mod slip;
+
+pub use self::receiver::{max_frame_size, to_frame, to_frame_with, FrameReceiver, Frames};
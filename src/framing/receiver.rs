@@ -0,0 +1,319 @@
+//! COBS frames with a CRC trailer, decoded incrementally
+//!
+//! `to_frame` serializes a value, appends its CRC and COBS-encodes the result.
+//! On the receiving side a `FrameReceiver` is fed the bytes as they arrive, a
+//! byte at a time from an interrupt or in chunks from DMA, and yields a value
+//! for every intact frame. Garbage and truncated frames are counted and
+//! skipped; the receiver picks up again after the next delimiter.
+//!
+//! ```
+//! # extern crate ubyte;
+//! use ubyte::framing::{self, FrameReceiver};
+//! use ubyte::framing::crc::Crc16;
+//!
+//! # fn main() {
+//! let mut frame = [0u8; framing::max_frame_size::<Crc16>(4)];
+//! let len = framing::to_frame::<Crc16, _>(&mut frame, &0x01020304u32).unwrap();
+//!
+//! let mut receiver = FrameReceiver::<16>::new();
+//! // noise before the first frame is dropped at the delimiter
+//! assert!(receiver.push::<u32>(0x55).is_none());
+//! assert!(receiver.push::<u32>(0).unwrap().is_err());
+//!
+//! let (last, rest) = frame[..len].split_last().unwrap();
+//! for &byte in rest {
+//!     assert!(receiver.push::<u32>(byte).is_none());
+//! }
+//! assert_eq!(receiver.push::<u32>(*last).unwrap().unwrap(), 0x01020304);
+//! assert_eq!((receiver.received(), receiver.corrupt()), (1, 1));
+//! # }
+//! ```
+
+use crate::dev_prefix::*;
+
+use core::marker::PhantomData;
+use core::slice;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+use crate::options::{DefaultOptions, Options};
+use crate::ser;
+
+use super::cobs::{self, Accumulator};
+use super::crc::{self, Crc, Crc16};
+
+/// The largest frame, including the delimiter, a payload of `payload` bytes
+/// and its CRC can be encoded to.
+pub const fn max_frame_size<C: Crc>(payload: usize) -> usize {
+    cobs::max_frame_size(payload + C::SIZE)
+}
+
+/// serialize the value into `buffer` followed by its CRC, encode both as a
+/// COBS frame and return the length of the frame.
+pub fn to_frame<C, T>(buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where C: Crc,
+          T: Serialize + ?Sized
+{
+    to_frame_with::<C, _, _>(DefaultOptions::new(), buffer, value)
+}
+
+/// Same as `to_frame` but using the encoding `options`.
+pub fn to_frame_with<C, O, T>(options: O, buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where C: Crc,
+          O: Options,
+          T: Serialize + ?Sized
+{
+    let len = ser::to_bytes_with(options, buffer, value)?;
+    let len = crc::append_with::<C, _>(options, buffer, len)?;
+    cobs::encode_in_place(buffer, len)
+}
+
+/// Decodes frames written by `to_frame` from a stream of bytes.
+///
+/// `N` must be able to hold a whole frame without its delimiter, i.e.
+/// `max_frame_size::<C>(T::MAX_SIZE) - 1`.
+pub struct FrameReceiver<const N: usize, C = Crc16, O = DefaultOptions> {
+    accumulator: Accumulator<N>,
+    options: O,
+    received: u32,
+    dropped: u32,
+    corrupt: u32,
+    crc: PhantomData<C>,
+}
+
+impl<const N: usize, C: Crc> FrameReceiver<N, C> {
+    /// Create a receiver using the default options.
+    pub fn new() -> Self {
+        FrameReceiver::new_with(DefaultOptions::new())
+    }
+}
+
+impl<const N: usize, C: Crc, O: Options> FrameReceiver<N, C, O> {
+    /// Create a receiver using the encoding `options`.
+    pub fn new_with(options: O) -> Self {
+        FrameReceiver {
+            accumulator: Accumulator::new(),
+            options,
+            received: 0,
+            dropped: 0,
+            corrupt: 0,
+            crc: PhantomData,
+        }
+    }
+
+    /// Add the next byte received.
+    ///
+    /// Returns the value once the delimiter of a frame arrives, or the error
+    /// if the frame didn't fit (`BufferSmall`), was corrupt or didn't decode.
+    /// Empty frames are skipped.
+    pub fn push<'a, T>(&'a mut self, byte: u8) -> Option<DeResult<T>>
+        where T: Deserialize<'a>
+    {
+        let options = self.options;
+        let result = match self.accumulator.push(byte)? {
+            Ok(frame) => crc::verify_with::<C, _>(options, frame)
+                .and_then(|payload| de::from_bytes_with(options, payload)),
+            Err(DeError::BufferSmall) => {
+                self.dropped = self.dropped.wrapping_add(1);
+                return Some(Err(DeError::BufferSmall));
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(_) => self.received = self.received.wrapping_add(1),
+            Err(_) => self.corrupt = self.corrupt.wrapping_add(1),
+        }
+        Some(result)
+    }
+
+    /// Add a chunk of bytes received, returning an iterator over the frames
+    /// that complete in it.
+    ///
+    /// Bytes after the last delimiter are kept for the next call. Dropping the
+    /// iterator early still feeds the rest of the chunk: the frames left are
+    /// counted, but their values are discarded.
+    pub fn feed<'a, 'c, T>(&'a mut self, chunk: &'c [u8]) -> Frames<'a, 'c, N, C, O, T>
+        where T: DeserializeOwned
+    {
+        Frames { receiver: self, chunk: chunk.iter(), value: PhantomData }
+    }
+
+    /// The number of frames decoded successfully.
+    pub fn received(&self) -> u32 {
+        self.received
+    }
+
+    /// The number of frames dropped because they didn't fit in the buffer.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// The number of frames rejected because their encoding or CRC was
+    /// corrupt or the value didn't decode.
+    pub fn corrupt(&self) -> u32 {
+        self.corrupt
+    }
+
+    /// Drop the bytes of the current frame and clear the counters.
+    pub fn reset(&mut self) {
+        self.accumulator.reset();
+        self.received = 0;
+        self.dropped = 0;
+        self.corrupt = 0;
+    }
+}
+
+impl<const N: usize, C: Crc> Default for FrameReceiver<N, C> {
+    fn default() -> Self {
+        FrameReceiver::new()
+    }
+}
+
+/// Iterator over the values of the frames completed by a chunk, returned by
+/// `FrameReceiver::feed`.
+pub struct Frames<'a, 'c, const N: usize, C, O, T>
+    where C: Crc,
+          O: Options,
+          T: DeserializeOwned
+{
+    receiver: &'a mut FrameReceiver<N, C, O>,
+    chunk: slice::Iter<'c, u8>,
+    value: PhantomData<T>,
+}
+
+impl<'a, 'c, const N: usize, C, O, T> Iterator for Frames<'a, 'c, N, C, O, T>
+    where C: Crc,
+          O: Options,
+          T: DeserializeOwned
+{
+    type Item = DeResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for &byte in &mut self.chunk {
+            if let Some(result) = self.receiver.push(byte) {
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+impl<'a, 'c, const N: usize, C, O, T> Drop for Frames<'a, 'c, N, C, O, T>
+    where C: Crc,
+          O: Options,
+          T: DeserializeOwned
+{
+    fn drop(&mut self) {
+        // keep the receiver in sync with the stream
+        self.for_each(drop);
+    }
+}
+
+#[cfg(test)]
+fn frame_of<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut buffer = [0u8; 64];
+    let len = to_frame::<Crc16, _>(&mut buffer, value).unwrap();
+    buffer[..len].to_vec()
+}
+
+#[test]
+fn test_receiver_frames() {
+    use crate::options::U8;
+
+    let mut buffer = [0u8; max_frame_size::<crc::Crc32>(6)];
+    let options = DefaultOptions::new().with_little_endian().with_length::<U8, 8>();
+    let len = to_frame_with::<crc::Crc32, _, _>(options, &mut buffer, &(0u16, "abc")).unwrap();
+    assert_eq!(buffer[len - 1], cobs::DELIMITER);
+    assert!(buffer[..len - 1].iter().all(|&b| b != cobs::DELIMITER));
+
+    let mut receiver = FrameReceiver::<16, crc::Crc32, _>::new_with(options);
+    for &byte in &buffer[..len - 1] {
+        assert!(receiver.push::<(u16, &str)>(byte).is_none());
+    }
+    assert_eq!(receiver.push::<(u16, &str)>(0).unwrap().unwrap(), (0, "abc"));
+    assert_eq!(receiver.received(), 1);
+
+    // the buffer is too small for the payload
+    assert_eq!(to_frame::<Crc16, _>(&mut [0u8; 7], &0u32).unwrap_err(), SerError::Overflow);
+}
+
+#[test]
+fn test_receiver_resync() {
+    let mut stream = vec![0x13, 0x37, 0xFF, 0];
+    stream.extend(frame_of(&1u32));
+    // a frame cut off partway through runs into the next one
+    let truncated = frame_of(&2u32);
+    stream.extend_from_slice(&truncated[..3]);
+    stream.extend(frame_of(&3u32));
+    // a bit flip is caught by the CRC
+    let mut flipped = frame_of(&4u32);
+    flipped[2] ^= 0x04;
+    stream.extend(flipped);
+    stream.extend(&[0, 0]);
+    stream.extend(frame_of(&5u32));
+
+    let mut receiver = FrameReceiver::<16>::new();
+    let values: Vec<_> = stream.iter().filter_map(|&b| receiver.push::<u32>(b)).collect();
+    assert_eq!(values, vec![
+        Err(DeError::InvalidFrame),
+        Ok(1),
+        Err(DeError::ChecksumMismatch),
+        Err(DeError::ChecksumMismatch),
+        Ok(5),
+    ]);
+    assert_eq!((receiver.received(), receiver.dropped(), receiver.corrupt()), (2, 0, 3));
+}
+
+#[test]
+fn test_receiver_overflow() {
+    let mut receiver = FrameReceiver::<8>::new();
+    let long = frame_of(&[7u8; 16]);
+    let values: Vec<_> = long.iter().filter_map(|&b| receiver.push::<[u8; 16]>(b)).collect();
+    assert_eq!(values, vec![Err(DeError::BufferSmall)]);
+    for &byte in &frame_of(&6u32) {
+        if let Some(value) = receiver.push::<u32>(byte) {
+            assert_eq!(value.unwrap(), 6);
+        }
+    }
+    assert_eq!((receiver.received(), receiver.dropped(), receiver.corrupt()), (1, 1, 0));
+
+    receiver.reset();
+    assert_eq!((receiver.received(), receiver.dropped(), receiver.corrupt()), (0, 0, 0));
+}
+
+#[test]
+fn test_receiver_feed() {
+    let mut stream = Vec::new();
+    for value in 0..10u32 {
+        stream.extend(frame_of(&(value * 1000)));
+    }
+    for size in 1..stream.len() {
+        let mut receiver = FrameReceiver::<16>::new();
+        let mut values = Vec::new();
+        for chunk in stream.chunks(size) {
+            for value in receiver.feed::<u32>(chunk) {
+                values.push(value.unwrap());
+            }
+        }
+        assert_eq!(values, (0..10).map(|v| v * 1000).collect::<Vec<_>>());
+        assert_eq!(receiver.received(), 10);
+    }
+}
+
+#[test]
+fn test_receiver_feed_drop() {
+    let mut stream = frame_of(&1u32);
+    stream.extend(frame_of(&2u32));
+    let third = frame_of(&3u32);
+    stream.extend_from_slice(&third[..2]);
+
+    // only the first frame is taken, the rest of the chunk is still fed
+    let mut receiver = FrameReceiver::<16>::new();
+    assert_eq!(receiver.feed::<u32>(&stream).next().unwrap().unwrap(), 1);
+    assert_eq!(receiver.received(), 2);
+    let values: Vec<_> = receiver.feed::<u32>(&third[2..]).collect();
+    assert_eq!(values, vec![Ok(3)]);
+    assert_eq!(receiver.received(), 3);
+}
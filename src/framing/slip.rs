@@ -0,0 +1,231 @@
+//! SLIP (RFC 1055) framing
+//!
+//! Each frame ends with an `END` byte. `END` and `ESC` bytes in the payload
+//! are escaped as two byte sequences, so a payload of `n` bytes takes up at
+//! most `max_frame_size(n)` bytes:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # #[macro_use] extern crate ubyte_derive;
+//! # extern crate ubyte;
+//! use ubyte::MaxSize;
+//! use ubyte::framing::slip;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize, MaxSize)]
+//! struct Reading {
+//!     channel: u8,
+//!     value: i32,
+//! }
+//!
+//! # fn main() {
+//! let mut frame = [0u8; slip::max_frame_size(Reading::MAX_SIZE)];
+//! let reading = Reading { channel: 3, value: 0xC0 };
+//! let len = slip::to_bytes(&mut frame, &reading).unwrap();
+//! assert_eq!(&frame[..len], &[3, 0, 0, 0, slip::ESC, slip::ESC_END, slip::END]);
+//! assert_eq!(slip::from_bytes::<Reading>(&mut frame[..len]).unwrap(), reading);
+//! # }
+//! ```
+
+use crate::dev_prefix::*;
+
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+use crate::options::{DefaultOptions, Options};
+use crate::ser;
+
+/// The frame delimiter.
+pub const END: u8 = 0xC0;
+/// Starts an escape sequence.
+pub const ESC: u8 = 0xDB;
+/// `ESC ESC_END` stands for an `END` byte in the payload.
+pub const ESC_END: u8 = 0xDC;
+/// `ESC ESC_ESC` stands for an `ESC` byte in the payload.
+pub const ESC_ESC: u8 = 0xDD;
+
+/// The largest frame, including the delimiter, a payload of `payload` bytes
+/// can be encoded to.
+pub const fn max_frame_size(payload: usize) -> usize {
+    // every byte may need escaping
+    2 * payload + 1
+}
+
+/// serialize the value into `buffer` as a SLIP frame, including the
+/// delimiter, and return the length of the frame.
+pub fn to_bytes<T>(buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where T: Serialize + ?Sized
+{
+    to_bytes_with(DefaultOptions::new(), buffer, value)
+}
+
+/// Same as `to_bytes` but using the encoding `options`.
+pub fn to_bytes_with<O, T>(options: O, buffer: &mut [u8], value: &T) -> SerResult<usize>
+    where O: Options,
+          T: Serialize + ?Sized
+{
+    let len = ser::to_bytes_with(options, buffer, value)?;
+    encode_in_place(buffer, len)
+}
+
+/// Encode the payload `buffer[..len]` in place as a SLIP frame, including the
+/// delimiter, and return the length of the frame.
+///
+/// Unlike COBS the size of the frame depends on the payload, so `buffer` only
+/// needs room for the escapes actually used, at most `max_frame_size(len)`.
+pub fn encode_in_place(buffer: &mut [u8], len: usize) -> SerResult<usize> {
+    let escapes = buffer[..len].iter().filter(|&&b| b == END || b == ESC).count();
+    let frame_len = len + escapes + 1;
+    if frame_len > buffer.len() {
+        return Err(SerError::Overflow);
+    }
+    // escaping from the back never overwrites bytes that haven't been read yet
+    let mut dst = frame_len - 1;
+    buffer[dst] = END;
+    for src in (0..len).rev() {
+        let byte = buffer[src];
+        let escaped = match byte {
+            END => ESC_END,
+            ESC => ESC_ESC,
+            _ => {
+                dst -= 1;
+                buffer[dst] = byte;
+                continue;
+            }
+        };
+        dst -= 2;
+        buffer[dst] = ESC;
+        buffer[dst + 1] = escaped;
+    }
+    Ok(frame_len)
+}
+
+/// Decode a SLIP frame in place, returning the length of the payload at the
+/// start of `frame`.
+///
+/// Leading delimiters, which senders use to flush line noise, and a trailing
+/// delimiter are ignored, so a frame of only delimiters is empty, as in
+/// `cobs::decode_in_place`.
+pub fn decode_in_place(frame: &mut [u8]) -> DeResult<usize> {
+    let start = match frame.iter().position(|&b| b != END) {
+        Some(start) => start,
+        None => return Ok(0),
+    };
+    let end = match frame.split_last() {
+        Some((&END, _)) => frame.len() - 1,
+        _ => frame.len(),
+    };
+    let mut src = start;
+    let mut dst = 0;
+    while src < end {
+        let byte = match frame[src] {
+            END => return Err(DeError::InvalidFrame),
+            ESC => {
+                src += 1;
+                match frame.get(src) {
+                    Some(&ESC_END) if src < end => END,
+                    Some(&ESC_ESC) if src < end => ESC,
+                    _ => return Err(DeError::InvalidFrame),
+                }
+            }
+            byte => byte,
+        };
+        frame[dst] = byte;
+        src += 1;
+        dst += 1;
+    }
+    Ok(dst)
+}
+
+/// Decode a SLIP frame in place and deserialize the payload.
+pub fn from_bytes<'de, T>(frame: &'de mut [u8]) -> DeResult<T>
+    where T: Deserialize<'de>
+{
+    from_bytes_with(DefaultOptions::new(), frame)
+}
+
+/// Same as `from_bytes` but using the encoding `options`.
+pub fn from_bytes_with<'de, O, T>(options: O, frame: &'de mut [u8]) -> DeResult<T>
+    where O: Options,
+          T: Deserialize<'de>
+{
+    let len = decode_in_place(frame)?;
+    let frame: &'de [u8] = frame;
+    de::from_bytes_with(options, &frame[..len])
+}
+
+#[test]
+fn test_slip_vectors() {
+    fn check(payload: &[u8], frame: &[u8]) {
+        let mut buffer = [0u8; 64];
+        buffer[..payload.len()].copy_from_slice(payload);
+        let len = encode_in_place(&mut buffer, payload.len()).unwrap();
+        assert_eq!(&buffer[..len], frame);
+        assert!(len <= max_frame_size(payload.len()));
+        assert_eq!(decode_in_place(&mut buffer[..len]).unwrap(), payload.len());
+        assert_eq!(&buffer[..payload.len()], payload);
+    }
+
+    check(&[], &[END]);
+    check(&[1, 2, 3], &[1, 2, 3, END]);
+    check(&[END], &[ESC, ESC_END, END]);
+    check(&[ESC], &[ESC, ESC_ESC, END]);
+    check(&[ESC_END, ESC_ESC], &[ESC_END, ESC_ESC, END]);
+    check(&[1, END, ESC, 2, END], &[1, ESC, ESC_END, ESC, ESC_ESC, 2, ESC, ESC_END, END]);
+    check(&[END; 8], &[ESC, ESC_END, ESC, ESC_END, ESC, ESC_END, ESC, ESC_END,
+                        ESC, ESC_END, ESC, ESC_END, ESC, ESC_END, ESC, ESC_END, END]);
+
+    // the delimiter is optional when the frame boundary is known
+    assert_eq!(decode_in_place(&mut [1, ESC, ESC_END]).unwrap(), 2);
+    assert_eq!(decode_in_place(&mut [1, END, 2, END]).unwrap_err(), DeError::InvalidFrame);
+    assert_eq!(decode_in_place(&mut [1, ESC, 2, END]).unwrap_err(), DeError::InvalidFrame);
+    assert_eq!(decode_in_place(&mut [1, ESC, END]).unwrap_err(), DeError::InvalidFrame);
+    assert_eq!(decode_in_place(&mut [1, ESC]).unwrap_err(), DeError::InvalidFrame);
+}
+
+#[test]
+fn test_slip_delimiters() {
+    // leading delimiters are skipped
+    let mut frame = [END, END, 1, ESC, ESC_ESC, 2, END];
+    assert_eq!(decode_in_place(&mut frame).unwrap(), 3);
+    assert_eq!(&frame[..3], &[1, ESC, 2]);
+    assert_eq!(decode_in_place(&mut [END, 7]).unwrap(), 1);
+
+    // frames of only delimiters are empty
+    assert_eq!(decode_in_place(&mut []).unwrap(), 0);
+    assert_eq!(decode_in_place(&mut [END]).unwrap(), 0);
+    assert_eq!(decode_in_place(&mut [END, END, END]).unwrap(), 0);
+    assert_eq!(from_bytes::<()>(&mut [END, END]).unwrap(), ());
+
+    let mut frame = [END; 8];
+    let len = to_bytes(&mut frame[1..], &0x1234u16).unwrap();
+    assert_eq!(from_bytes::<u16>(&mut frame[..len + 1]).unwrap(), 0x1234);
+}
+
+#[test]
+fn test_slip_value() {
+    use crate::max_size::MaxSize;
+    use crate::options::{Config, U16};
+
+    let mut frame = [0u8; max_frame_size(<(u8, u32) as MaxSize>::MAX_SIZE)];
+    let len = to_bytes(&mut frame, &(0xDBu8, 0xC0u32)).unwrap();
+    assert_eq!(&frame[..len], &[ESC, ESC_ESC, 0, 0, 0, ESC, ESC_END, END]);
+    assert_eq!(from_bytes::<(u8, u32)>(&mut frame[..len]).unwrap(), (0xDB, 0xC0));
+
+    // the payload fits but the escaped frame doesn't
+    assert_eq!(to_bytes(&mut frame[..5], &(1u8, 2u32)).unwrap_err(), SerError::Overflow);
+    assert_eq!(to_bytes(&mut frame[..6], &(1u8, 2u32)).unwrap(), 6);
+    assert_eq!(to_bytes(&mut frame[..6], &(END, 2u32)).unwrap_err(), SerError::Overflow);
+
+    let options = Config::new().with_length::<U16, 1000>();
+    let payload: Vec<u8> = (0..700).map(|i| (0xBE + i % 32) as u8).collect();
+    let mut frame = [0u8; max_frame_size(2 + 700)];
+    let len = to_bytes_with(options, &mut frame, &payload).unwrap();
+    assert!(!frame[..len - 1].contains(&END));
+    assert_eq!(frame[len - 1], END);
+    assert_eq!(from_bytes_with::<_, Vec<u8>>(options, &mut frame[..len]).unwrap(), payload);
+
+    let payload = vec![ESC; 700];
+    let len = to_bytes_with(options, &mut frame, &payload).unwrap();
+    assert_eq!(len, max_frame_size(2 + 700) - 2);
+    assert_eq!(from_bytes_with::<_, Vec<u8>>(options, &mut frame[..len]).unwrap(), payload);
+}
@@ -1,28 +1,105 @@
-//! The ubin project is very similar to the bincode library. The intent is to create an ultra
-//! simple and lightweight serialization library for microcontrollers serialization library for
-//! microcontrollers and other memory constrained devices.
+//! ubyte is a serde serialization library for microcontrollers and other
+//! memory constrained devices. It is similar to bincode, but every type it
+//! encodes has a known maximum size (see `MaxSize`).
 //!
-//! Similar to bincode, it has the following caveots
-//! -   isize/usize are encoded as i64/u64, for portability.    enums variants are encoded as a u32
-//! -   instead of a usize. u32 is enough for all practical uses.    str is encoded as (u64,
-//! -   &[u8]), where the u64 is the number of bytes contained in the encoded string.
+//! The encoding is picked with `Options`, built with `Config`. With the
+//! defaults (`DefaultOptions`):
+//! -   integers and floats are fixed width (`Config::with_varint_encoding`)
+//!     and big endian (`Config::with_little_endian`). isize/usize are encoded
+//!     as i64/u64, `Isize`/`Usize` as wide as `Config::with_usize` sets (8
+//!     bytes by default).
+//! -   enum variants are encoded as a `u8` index (`Config::with_tag`), or as
+//!     their tag from `EnumTags` when wrapped in `Tagged`.
+//! -   str, byte slices and sequences are not supported. `Config::with_length`
+//!     adds a length prefix, i.e. a str is encoded as `(u8, &[u8])` with
+//!     `with_length::<U8, N>()`, where the u8 is the number of bytes and at
+//!     most `N`.
+//!
+//! Newtype structs and enums named `ubyte::...` are reserved: the serializer
+//! and deserializer handle ubyte's own wrapper types through them.
 
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+#![forbid(unsafe_code)]
 
-#[cfg(feature = "std")]
+#[cfg(any(feature = "std", test))]
 extern crate core;
 
 extern crate serde;
 extern crate byteorder;
+#[cfg(feature = "half")]
+extern crate half;
+#[cfg(feature = "tokio")]
+extern crate bytes;
+#[cfg(feature = "tokio")]
+extern crate tokio_util;
+#[cfg(feature = "embedded-io")]
+extern crate embedded_io;
+#[cfg(feature = "embedded-io-async")]
+extern crate embedded_io_async;
 
 #[cfg(test)]
 #[macro_use]
 extern crate serde_derive;
+#[cfg(test)]
+extern crate serde_bytes;
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
+#[cfg(all(test, any(feature = "tokio", feature = "embedded-io-async")))]
+extern crate futures;
+#[cfg(all(test, feature = "tokio"))]
+extern crate tokio;
+
+#[cfg(any(test, feature = "derive"))]
+#[allow(unused_imports)]
+#[macro_use]
+extern crate ubyte_derive;
+
+// lets the derive macros refer to `::ubyte` from within this crate's tests
+#[cfg(test)]
+extern crate self as ubyte;
 
+#[cfg(feature = "tokio")]
+pub mod codec;
 mod dev_prefix;
+#[cfg(feature = "embedded-io")]
+pub mod embedded;
 mod error;
+#[cfg(feature = "half")]
+mod float;
+mod packet;
+mod varint;
 pub mod de;
+pub mod framing;
+pub mod max_size;
+pub mod options;
 pub mod ser;
+pub mod size;
+pub mod tagged;
+
+pub use crate::ser::{to_bytes, to_bytes_with, serialized_size, serialized_size_with, to_sink,
+                     to_sink_with, ByteSink, Serializer};
+#[cfg(feature = "std")]
+pub use crate::ser::{to_vec, to_vec_with, to_writer, to_writer_with};
+pub use crate::de::{from_bytes, from_bytes_with, from_bytes_context, from_bytes_context_with,
+             take_from_bytes, take_from_bytes_with, iter_from_bytes, iter_from_bytes_with,
+             Deserializer};
+#[cfg(feature = "std")]
+pub use crate::de::{from_reader, from_reader_with};
+pub use crate::max_size::{MaxSize, MaxSizeWith};
+pub use crate::options::{Config, DefaultOptions, Options};
+pub use crate::packet::Packet;
+pub use crate::size::{Isize, Usize};
+pub use crate::tagged::{EnumTags, Tagged};
+pub use crate::varint::Varint;
+#[cfg(feature = "half")]
+pub use crate::float::F16;
+#[cfg(feature = "tokio")]
+pub use crate::codec::UbyteCodec;
+pub use crate::error::{SerError, SerResult, DeError, DeResult, ErrorContext, Message, MSG_CAPACITY,
+                SinkError, Unsupported};
+#[cfg(feature = "path")]
+pub use crate::error::{Path, PATH_DEPTH};
 
-pub use ser::{to_bytes, Serializer};
-pub use de::{from_bytes, Deserializer};
+#[cfg(feature = "derive")]
+pub use ubyte_derive::{EnumTags, MaxSize};
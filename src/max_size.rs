@@ -0,0 +1,303 @@
+//! maximum serialized size module
+//!
+//! Every type that can be serialized has a known upper bound on the number of
+//! bytes it takes up. `MaxSize` exposes that bound as an associated const so
+//! buffers can be sized at compile time:
+//!
+//! ```
+//! use ubyte::MaxSize;
+//!
+//! let mut buffer = [0u8; <(u32, Option<u16>) as MaxSize>::MAX_SIZE];
+//! let len = ubyte::to_bytes(&mut buffer, &(1u32, Some(2u16))).unwrap();
+//! assert_eq!(len, 7);
+//! ```
+//!
+//! The size depends on the encoding `Options`, `MaxSizeWith<O>` gives it for
+//! any options while `MaxSize` is the shorthand for `DefaultOptions`.
+//! Implement `MaxSizeWith` for all options to get both, or use
+//! `#[derive(MaxSize)]` on structs and enums (requires the `derive` feature).
+//!
+//! `MaxSize` is implemented for every `MaxSizeWith<DefaultOptions>` type, so
+//! it can no longer be implemented directly: an existing `impl MaxSize for T`
+//! becomes `impl<O: Options> MaxSizeWith<O> for T`.
+//!
+//! Strings, byte slices and sequences are only bounded once a length prefix
+//! is set with `Config::with_length`; without one their size fails to compile:
+//!
+//! ```compile_fail
+//! use ubyte::MaxSize;
+//!
+//! let buffer = [0u8; <&str as MaxSize>::MAX_SIZE];
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::options::{DefaultOptions, IntEncoding, LengthPrefix, Options, TagWidth};
+use crate::varint;
+
+/// A type with a known maximum serialized size when using `DefaultOptions`.
+///
+/// Implemented through `MaxSizeWith<DefaultOptions>`, implement that instead.
+pub trait MaxSize {
+    /// The largest number of bytes `to_bytes` can write for this type.
+    const MAX_SIZE: usize;
+}
+
+impl<T: MaxSizeWith<DefaultOptions> + ?Sized> MaxSize for T {
+    const MAX_SIZE: usize = <T as MaxSizeWith<DefaultOptions>>::MAX_SIZE;
+}
+
+/// A type with a known maximum serialized size when using the options `O`.
+pub trait MaxSizeWith<O: Options> {
+    /// The largest number of bytes `to_bytes_with` can write for this type.
+    const MAX_SIZE: usize;
+}
+
+/// `const` version of `cmp::max`, used to size enums.
+#[doc(hidden)]
+pub const fn max(a: usize, b: usize) -> usize {
+    if a > b { a } else { b }
+}
+
+/// The size of an integer that is `bytes` wide, varints can be larger.
+#[doc(hidden)]
+pub const fn int_size<O: Options>(bytes: usize) -> usize {
+    if bytes > 1 && O::Ints::VARINT {
+        varint::max_len(bytes)
+    } else {
+        bytes
+    }
+}
+
+/// The size of a length prefix. Fails to compile when the prefix is
+/// `Disabled`, as nothing variable length can be encoded then.
+#[doc(hidden)]
+pub const fn len_size<O: Options>() -> usize {
+    assert!(O::Length::ENABLED, "variable length types need `Config::with_length`");
+    int_size::<O>(O::Length::MAX_SIZE)
+}
+
+/// The size of an enum tag.
+#[doc(hidden)]
+pub const fn tag_size<O: Options>() -> usize {
+    if O::Tag::VARINT {
+        O::Tag::MAX_SIZE
+    } else {
+        int_size::<O>(O::Tag::MAX_SIZE)
+    }
+}
+
+macro_rules! impl_max_size {
+    ($($ty:ty => $size:expr),* $(,)*) => {
+        $(
+            impl<O: Options> MaxSizeWith<O> for $ty {
+                const MAX_SIZE: usize = $size;
+            }
+        )*
+    }
+}
+
+impl_max_size! {
+    bool => 1,
+    u8 => 1,
+    i8 => 1,
+    u16 => int_size::<O>(2),
+    i16 => int_size::<O>(2),
+    u32 => int_size::<O>(4),
+    i32 => int_size::<O>(4),
+    u64 => int_size::<O>(8),
+    i64 => int_size::<O>(8),
+    // serde always encodes usize/isize as u64/i64
+    usize => int_size::<O>(8),
+    isize => int_size::<O>(8),
+    // always fixed width
+    u128 => 16,
+    i128 => 16,
+    // UTF-8
+    char => 4,
+    f32 => 4,
+    f64 => 8,
+    () => 0,
+}
+
+impl<O: Options, T: ?Sized> MaxSizeWith<O> for PhantomData<T> {
+    const MAX_SIZE: usize = 0;
+}
+
+impl<O: Options, T: MaxSizeWith<O> + ?Sized> MaxSizeWith<O> for &T {
+    const MAX_SIZE: usize = T::MAX_SIZE;
+}
+
+impl<O: Options, T: MaxSizeWith<O> + ?Sized> MaxSizeWith<O> for &mut T {
+    const MAX_SIZE: usize = T::MAX_SIZE;
+}
+
+/// `None` is the variant byte, `Some` is the variant byte followed by the value
+impl<O: Options, T: MaxSizeWith<O>> MaxSizeWith<O> for Option<T> {
+    const MAX_SIZE: usize = 1 + T::MAX_SIZE;
+}
+
+impl<O: Options, T: MaxSizeWith<O>, const N: usize> MaxSizeWith<O> for [T; N] {
+    const MAX_SIZE: usize = N * T::MAX_SIZE;
+}
+
+// variable length types: the length prefix followed by at most `MAX_LEN`
+// bytes or elements. They have no size without a length prefix.
+
+impl<O: Options> MaxSizeWith<O> for str {
+    const MAX_SIZE: usize = len_size::<O>() + O::MAX_LEN;
+}
+
+impl<O: Options, T: MaxSizeWith<O>> MaxSizeWith<O> for [T] {
+    const MAX_SIZE: usize = len_size::<O>() + O::MAX_LEN * T::MAX_SIZE;
+}
+
+#[cfg(feature = "std")]
+impl<O: Options> MaxSizeWith<O> for String {
+    const MAX_SIZE: usize = <str as MaxSizeWith<O>>::MAX_SIZE;
+}
+
+#[cfg(feature = "std")]
+impl<O: Options, T: MaxSizeWith<O>> MaxSizeWith<O> for Vec<T> {
+    const MAX_SIZE: usize = <[T] as MaxSizeWith<O>>::MAX_SIZE;
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident)+) => {
+        impl<Opt: Options, $($name: MaxSizeWith<Opt>),+> MaxSizeWith<Opt> for ($($name,)+) {
+            const MAX_SIZE: usize = 0 $(+ $name::MAX_SIZE)+;
+        }
+    }
+}
+
+impl_tuple!(A);
+impl_tuple!(A B);
+impl_tuple!(A B C);
+impl_tuple!(A B C D);
+impl_tuple!(A B C D E);
+impl_tuple!(A B C D E F);
+impl_tuple!(A B C D E F G);
+impl_tuple!(A B C D E F G H);
+impl_tuple!(A B C D E F G H I);
+impl_tuple!(A B C D E F G H I J);
+impl_tuple!(A B C D E F G H I J K);
+impl_tuple!(A B C D E F G H I J K L);
+impl_tuple!(A B C D E F G H I J K L M);
+impl_tuple!(A B C D E F G H I J K L M N);
+impl_tuple!(A B C D E F G H I J K L M N O);
+impl_tuple!(A B C D E F G H I J K L M N O P);
+
+#[test]
+fn test_max_size_primitives() {
+    use crate::ser::to_bytes;
+
+    assert_eq!(<(u8, i16, u32, i64) as MaxSize>::MAX_SIZE, 15);
+    assert_eq!(<(f32, f64, bool, ()) as MaxSize>::MAX_SIZE, 13);
+    assert_eq!(<Option<u32> as MaxSize>::MAX_SIZE, 5);
+    assert_eq!(<[Option<u16>; 4] as MaxSize>::MAX_SIZE, 12);
+
+    let value = (Some(0xFFu8), [1u16, 2, 3], -1i64);
+    let mut buffer = [0u8; <(Option<u8>, [u16; 3], i64) as MaxSize>::MAX_SIZE];
+    assert_eq!(to_bytes(&mut buffer, &value).unwrap(), buffer.len());
+}
+
+#[test]
+fn test_max_size_derive() {
+    use crate::ser::to_bytes;
+
+    #[derive(Serialize, MaxSize)]
+    struct Unit;
+
+    #[derive(Serialize, MaxSize)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Serialize, MaxSize)]
+    struct Wrapper<T>(T, u8);
+
+    #[allow(dead_code)]
+    #[derive(Serialize, MaxSize)]
+    enum E {
+        Unit,
+        Newtype(u32),
+        Tuple(u32, u64),
+        Struct { a: Point, b: Option<u8> },
+    }
+
+    assert_eq!(<Unit as MaxSize>::MAX_SIZE, 0);
+    assert_eq!(<Point as MaxSize>::MAX_SIZE, 8);
+    assert_eq!(<Wrapper<u16> as MaxSize>::MAX_SIZE, 3);
+    assert_eq!(<E as MaxSize>::MAX_SIZE, 1 + 12);
+
+    let mut buffer = [0u8; <E as MaxSize>::MAX_SIZE];
+    let value = E::Tuple(1, 2);
+    assert_eq!(to_bytes(&mut buffer, &value).unwrap(), 13);
+    let value = E::Struct { a: Point { x: 1, y: 2 }, b: Some(3) };
+    assert_eq!(to_bytes(&mut buffer, &value).unwrap(), 11);
+}
+
+#[test]
+fn test_max_size_length_prefixed() {
+    use crate::options::{BigEndian, Config, U8};
+    use crate::ser::to_bytes_with;
+
+    type Options = Config<BigEndian, U8, U8, 8>;
+    let options: Options = Config::new().with_length();
+
+    #[derive(Serialize, MaxSize)]
+    struct Named<'a> {
+        name: &'a str,
+        readings: &'a [u16],
+    }
+
+    assert_eq!(<Named as MaxSizeWith<Options>>::MAX_SIZE, (1 + 8) + (1 + 8 * 2));
+
+    let mut buffer = [0u8; <Named as MaxSizeWith<Options>>::MAX_SIZE];
+    let value = Named { name: "12345678", readings: &[7; 8] };
+    assert_eq!(to_bytes_with(options, &mut buffer, &value).unwrap(), buffer.len());
+}
+
+#[test]
+fn test_max_size_options() {
+    use crate::options::{Config, Disabled, LittleEndian, U16, U32, VarintEncoding, VarintTag};
+
+    #[allow(dead_code)]
+    #[derive(MaxSize)]
+    enum E {
+        A,
+        B(u16, Option<u8>),
+    }
+
+    assert_eq!(<E as MaxSize>::MAX_SIZE, 1 + 4);
+    assert_eq!(<E as MaxSizeWith<Config<LittleEndian, U16>>>::MAX_SIZE, 2 + 4);
+    assert_eq!(<E as MaxSizeWith<Config<LittleEndian, U32>>>::MAX_SIZE, 4 + 4);
+    assert_eq!(<E as MaxSizeWith<Config<LittleEndian, VarintTag>>>::MAX_SIZE, 5 + 4);
+    type Varints = Config<LittleEndian, VarintTag, Disabled, 0, VarintEncoding>;
+    assert_eq!(<E as MaxSizeWith<Varints>>::MAX_SIZE, 5 + 3 + 1 + 1);
+}
+
+#[test]
+fn test_max_size_varint() {
+    use crate::options::{BigEndian, Config, U16, VarintEncoding};
+    use crate::ser::to_bytes_with;
+
+    type Options = Config<BigEndian, U16, U16, 300, VarintEncoding>;
+
+    #[allow(dead_code)]
+    #[derive(Serialize, MaxSize)]
+    enum E<'a> {
+        A(u8, u16, i32, u64),
+        B(&'a [u8]),
+    }
+
+    assert_eq!(<E as MaxSizeWith<Options>>::MAX_SIZE, 3 + max(1 + 3 + 5 + 10, 3 + 300));
+
+    let options = Config::new().with_tag::<U16>().with_length::<U16, 300>().with_varint_encoding();
+    let mut buffer = [0u8; <E as MaxSizeWith<Options>>::MAX_SIZE];
+    let value = E::A(u8::MAX, u16::MAX, i32::MIN, u64::MAX);
+    assert_eq!(to_bytes_with(options, &mut buffer, &value).unwrap(), 1 + 1 + 3 + 5 + 10);
+    let value = E::B(&[0; 300]);
+    assert_eq!(to_bytes_with(options, &mut buffer, &value).unwrap(), 1 + 2 + 300);
+}
@@ -0,0 +1,380 @@
+//! encoding options module
+//!
+//! The encoding is selected at compile time through an `Options` type, so the
+//! serializer and deserializer are monomorphized for it with no runtime cost.
+//! `to_bytes`/`from_bytes` use `DefaultOptions`; other options are built from
+//! `Config` and passed to `to_bytes_with`/`from_bytes_with`:
+//!
+//! ```
+//! use ubyte::options::{Config, U8};
+//!
+//! // strings, byte slices and sequences of up to 16 elements, prefixed
+//! // with their length as a u8
+//! let options = Config::new().with_length::<U8, 16>();
+//!
+//! let mut buffer = [0u8; 32];
+//! let len = ubyte::to_bytes_with(options, &mut buffer, &("dev-7", 3u8)).unwrap();
+//! assert_eq!(&buffer[..len], b"\x05dev-7\x03");
+//!
+//! let value: (&str, u8) = ubyte::from_bytes_with(options, &buffer[..len]).unwrap();
+//! assert_eq!(value, ("dev-7", 3));
+//! ```
+//!
+//! The byte order and the width of enum variant tags can be changed the same
+//! way, i.e. to talk to a little endian peer using `u16` tags:
+//!
+//! ```
+//! use ubyte::options::{Config, U16};
+//!
+//! let options = Config::new().with_little_endian().with_tag::<U16>();
+//!
+//! let mut buffer = [0u8; 8];
+//! let len = ubyte::to_bytes_with(options, &mut buffer, &(0x0102u16, Some(3u8))).unwrap();
+//! assert_eq!(&buffer[..len], &[0x02, 0x01, 1, 3]);
+//! ```
+//!
+//! With `Config::with_varint_encoding` integers wider than a byte are written
+//! as LEB128 varints (zigzag encoded if signed), see the `varint` module.
+//! This includes length prefixes and enum tags wider than a byte.
+//!
+//! `usize` and `isize` are always encoded as 8 bytes: serde widens them to
+//! `u64`/`i64` before they reach the serializer. Wrap them in `Usize`/`Isize`
+//! to use the width set by `Config::with_usize` instead.
+
+use core::marker::PhantomData;
+
+use byteorder::ByteOrder;
+use serde::{de, ser, Deserialize, Serialize};
+
+use crate::max_size::MaxSizeWith;
+use crate::varint::Varint;
+
+pub use byteorder::{BigEndian, LittleEndian};
+
+/// The compile-time encoding options.
+///
+/// Options carry no data, so they are `Copy` and can be passed on freely.
+pub trait Options: Copy {
+    /// The byte order of integers and floats.
+    type Endian: ByteOrder;
+
+    /// How the variant index of enums is encoded.
+    type Tag: TagWidth;
+
+    /// Whether integers are written with a fixed width or as varints.
+    type Ints: IntEncoding;
+
+    /// How the length of strings, byte slices and sequences is encoded.
+    type Length: LengthPrefix;
+
+    /// The width of `Usize` and `Isize`.
+    type Usize: UsizeWidth;
+
+    /// The largest number of bytes (strings, byte slices) or elements
+    /// (sequences) that may be encoded or decoded.
+    const MAX_LEN: usize;
+}
+
+/// The options used by `to_bytes` and `from_bytes`: big endian, fixed width
+/// integers, one byte enum tags and variable length types are not supported.
+pub type DefaultOptions = Config;
+
+/// Builder for `Options`.
+///
+/// Every setting is a type parameter, so a `Config` is zero sized and
+/// `Copy`.
+pub struct Config<E = BigEndian, T = U8, L = Disabled, const MAX_LEN: usize = 0,
+                  I = FixintEncoding, U = U64> {
+    settings: PhantomData<(E, T, L, I, U)>,
+}
+
+impl Config {
+    /// The default options.
+    pub const fn new() -> Config {
+        Config { settings: PhantomData }
+    }
+}
+
+impl<E, T, L, const MAX_LEN: usize, I, U> Config<E, T, L, MAX_LEN, I, U> {
+    /// Encode integers and floats most significant byte first.
+    pub const fn with_big_endian(self) -> Config<BigEndian, T, L, MAX_LEN, I, U> {
+        Config { settings: PhantomData }
+    }
+
+    /// Encode integers and floats least significant byte first.
+    pub const fn with_little_endian(self) -> Config<LittleEndian, T, L, MAX_LEN, I, U> {
+        Config { settings: PhantomData }
+    }
+
+    /// Encode enum variant tags as `T2`: `U8`, `U16`, `U32` or `VarintTag`.
+    ///
+    /// Serializing a variant whose index doesn't fit returns
+    /// `SerError::EnumLarge`.
+    pub const fn with_tag<T2: TagWidth>(self) -> Config<E, T2, L, MAX_LEN, I, U> {
+        Config { settings: PhantomData }
+    }
+
+    /// Support strings, byte slices and sequences of up to `MAX` bytes or
+    /// elements, prefixed by their length encoded as `L2`.
+    pub const fn with_length<L2: LengthPrefix, const MAX: usize>(self)
+        -> Config<E, T, L2, MAX, I, U>
+    {
+        Config { settings: PhantomData }
+    }
+
+    /// Write integers wider than a byte with their full width.
+    pub const fn with_fixint_encoding(self) -> Config<E, T, L, MAX_LEN, FixintEncoding, U> {
+        Config { settings: PhantomData }
+    }
+
+    /// Write integers wider than a byte as varints.
+    pub const fn with_varint_encoding(self) -> Config<E, T, L, MAX_LEN, VarintEncoding, U> {
+        Config { settings: PhantomData }
+    }
+
+    /// Encode `Usize` and `Isize` as `U2`: `U16`, `U32` or `U64`.
+    ///
+    /// Plain `usize`/`isize` stay 8 bytes wide. Serializing a value that
+    /// doesn't fit returns `SerError::IntLarge`.
+    pub const fn with_usize<U2: UsizeWidth>(self) -> Config<E, T, L, MAX_LEN, I, U2> {
+        Config { settings: PhantomData }
+    }
+}
+
+impl<E, T, L, const MAX_LEN: usize, I, U> Options for Config<E, T, L, MAX_LEN, I, U>
+    where E: ByteOrder,
+          T: TagWidth,
+          L: LengthPrefix,
+          I: IntEncoding,
+          U: UsizeWidth
+{
+    type Endian = E;
+    type Tag = T;
+    type Ints = I;
+    type Length = L;
+    type Usize = U;
+
+    const MAX_LEN: usize = {
+        assert!(MAX_LEN <= L::MAX, "MAX_LEN can't be encoded in the length prefix");
+        MAX_LEN
+    };
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+impl<E, T, L, const MAX_LEN: usize, I, U> Clone for Config<E, T, L, MAX_LEN, I, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E, T, L, const MAX_LEN: usize, I, U> Copy for Config<E, T, L, MAX_LEN, I, U> {}
+
+/// `O` with `FixintEncoding`, for values that never use varints.
+#[cfg(feature = "half")]
+#[derive(Clone, Copy)]
+pub(crate) struct Fixint<O>(PhantomData<O>);
+
+#[cfg(feature = "half")]
+impl<O: Options> Options for Fixint<O> {
+    type Endian = O::Endian;
+    type Tag = O::Tag;
+    type Ints = FixintEncoding;
+    type Length = O::Length;
+    type Usize = O::Usize;
+
+    const MAX_LEN: usize = O::MAX_LEN;
+}
+
+/// How integers wider than a byte are written.
+pub trait IntEncoding {
+    #[doc(hidden)]
+    const VARINT: bool;
+}
+
+/// Integers take up their full width, in the configured byte order.
+#[derive(Clone, Copy, Debug)]
+pub enum FixintEncoding {}
+
+impl IntEncoding for FixintEncoding {
+    const VARINT: bool = false;
+}
+
+/// Integers are LEB128 varints, zigzag encoded if signed. The byte order
+/// only applies to floats.
+#[derive(Clone, Copy, Debug)]
+pub enum VarintEncoding {}
+
+impl IntEncoding for VarintEncoding {
+    const VARINT: bool = true;
+}
+
+/// The encoding of an enum variant tag.
+///
+/// `Option` is not affected, it always uses a single byte.
+pub trait TagWidth {
+    /// The number of bytes the tag takes up, before `VarintEncoding` is
+    /// applied.
+    const MAX_SIZE: usize;
+
+    /// Whether the tag is always a varint, whatever the integer encoding.
+    #[doc(hidden)]
+    const VARINT: bool = false;
+
+    /// The largest variant index the tag can hold.
+    const MAX: u32;
+
+    #[doc(hidden)]
+    fn serialize<S: ser::Serializer>(index: u32, serializer: S) -> Result<S::Ok, S::Error>;
+
+    #[doc(hidden)]
+    fn deserialize<'de, D: de::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error>;
+}
+
+/// The encoding of a length prefix.
+pub trait LengthPrefix {
+    /// Whether variable length types are supported at all.
+    const ENABLED: bool = true;
+
+    /// The number of bytes the prefix takes up.
+    const MAX_SIZE: usize;
+
+    /// The largest length the prefix can hold.
+    const MAX: usize;
+
+    #[doc(hidden)]
+    fn serialize<S: ser::Serializer>(len: usize, serializer: S) -> Result<S::Ok, S::Error>;
+
+    #[doc(hidden)]
+    fn deserialize<'de, D: de::Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error>;
+}
+
+/// Strings, byte slices and sequences are not supported.
+#[derive(Clone, Copy, Debug)]
+pub enum Disabled {}
+
+impl LengthPrefix for Disabled {
+    const ENABLED: bool = false;
+    const MAX_SIZE: usize = 0;
+    const MAX: usize = 0;
+
+    fn serialize<S: ser::Serializer>(_len: usize, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("variable length types are disabled"))
+    }
+
+    fn deserialize<'de, D: de::Deserializer<'de>>(_deserializer: D) -> Result<usize, D::Error> {
+        Err(de::Error::custom("variable length types are disabled"))
+    }
+}
+
+macro_rules! impl_width {
+    ($(#[$attr:meta])* $name:ident, $ty:ty) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug)]
+        pub enum $name {}
+
+        impl LengthPrefix for $name {
+            const MAX_SIZE: usize = ::core::mem::size_of::<$ty>();
+            const MAX: usize = <$ty>::MAX as usize;
+
+            #[inline(always)]
+            fn serialize<S: ser::Serializer>(len: usize, serializer: S) -> Result<S::Ok, S::Error> {
+                (len as $ty).serialize(serializer)
+            }
+
+            #[inline(always)]
+            fn deserialize<'de, D: de::Deserializer<'de>>(deserializer: D)
+                -> Result<usize, D::Error>
+            {
+                Ok(<$ty>::deserialize(deserializer)? as usize)
+            }
+        }
+
+        impl TagWidth for $name {
+            const MAX_SIZE: usize = ::core::mem::size_of::<$ty>();
+            const MAX: u32 = <$ty>::MAX as u32;
+
+            #[inline(always)]
+            fn serialize<S: ser::Serializer>(index: u32, serializer: S) -> Result<S::Ok, S::Error> {
+                (index as $ty).serialize(serializer)
+            }
+
+            #[inline(always)]
+            fn deserialize<'de, D: de::Deserializer<'de>>(deserializer: D)
+                -> Result<u32, D::Error>
+            {
+                Ok(<$ty>::deserialize(deserializer)? as u32)
+            }
+        }
+    }
+}
+
+impl_width!(/// A one byte length prefix or enum tag.
+                    U8, u8);
+impl_width!(/// A two byte length prefix, enum tag or `Usize`.
+                    U16, u16);
+impl_width!(/// A four byte length prefix, enum tag or `Usize`.
+                    U32, u32);
+
+/// The width of `Usize` and `Isize`, see `Config::with_usize`.
+pub trait UsizeWidth {
+    /// The number of bytes a `Usize` or `Isize` takes up.
+    const MAX_SIZE: usize;
+
+    /// Whether `v` fits in the width.
+    #[doc(hidden)]
+    #[inline(always)]
+    fn fits_unsigned(v: u64) -> bool {
+        Self::MAX_SIZE >= 8 || v >> (8 * Self::MAX_SIZE) == 0
+    }
+
+    /// Whether `v` fits in the width.
+    #[doc(hidden)]
+    #[inline(always)]
+    fn fits_signed(v: i64) -> bool {
+        let shift = 64 - 8 * Self::MAX_SIZE as u32;
+        (v << shift) >> shift == v
+    }
+}
+
+/// An eight byte `Usize`/`Isize`, the default.
+#[derive(Clone, Copy, Debug)]
+pub enum U64 {}
+
+impl UsizeWidth for U16 {
+    const MAX_SIZE: usize = 2;
+}
+
+impl UsizeWidth for U32 {
+    const MAX_SIZE: usize = 4;
+}
+
+impl UsizeWidth for U64 {
+    const MAX_SIZE: usize = 8;
+}
+
+/// A varint enum tag, 1 to 5 bytes: indexes below 128 take up a single byte.
+///
+/// Unlike `VarintEncoding` this only applies to enum tags.
+#[derive(Clone, Copy, Debug)]
+pub enum VarintTag {}
+
+impl TagWidth for VarintTag {
+    const MAX_SIZE: usize = <Varint<u32> as MaxSizeWith<Config>>::MAX_SIZE;
+    const VARINT: bool = true;
+    const MAX: u32 = u32::MAX;
+
+    #[inline(always)]
+    fn serialize<S: ser::Serializer>(index: u32, serializer: S) -> Result<S::Ok, S::Error> {
+        Varint(index).serialize(serializer)
+    }
+
+    #[inline(always)]
+    fn deserialize<'de, D: de::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        Ok(Varint::<u32>::deserialize(deserializer)?.0)
+    }
+}
@@ -0,0 +1,182 @@
+//! typed fixed-capacity buffer module
+//!
+//! A `Packet<T, N>` owns a `[u8; N]` together with the number of bytes used, and
+//! only encodes/decodes values of type `T`. `N` is checked against
+//! `T::MAX_SIZE` at compile time, so the buffer can never be too small:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # #[macro_use] extern crate ubyte_derive;
+//! # extern crate ubyte;
+//! use ubyte::{MaxSize, Packet};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize, MaxSize)]
+//! struct Reading {
+//!     channel: u8,
+//!     value: i32,
+//! }
+//!
+//! type ReadingPacket = Packet<Reading, { Reading::MAX_SIZE }>;
+//!
+//! # fn main() {
+//! let reading = Reading { channel: 3, value: -40 };
+//! let packet = ReadingPacket::from_value(&reading).unwrap();
+//! assert_eq!(packet.as_bytes(), &[3, 0xFF, 0xFF, 0xFF, 0xD8]);
+//! assert_eq!(packet.decode().unwrap(), reading);
+//! # }
+//! ```
+
+use crate::dev_prefix::*;
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use crate::de::from_bytes;
+use crate::max_size::MaxSize;
+use crate::ser::to_bytes;
+
+/// A buffer holding the encoded bytes of a single `T`.
+///
+/// `N` must be at least `T::MAX_SIZE`; this is checked when the packet is
+/// created. Use `Packet<T, { T::MAX_SIZE }>` for concrete types.
+pub struct Packet<T, const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: MaxSize, const N: usize> Packet<T, N> {
+    const ASSERT_CAPACITY: () = assert!(N >= T::MAX_SIZE,
+                                        "Packet capacity is smaller than T::MAX_SIZE");
+
+    /// Create an empty packet.
+    pub fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::ASSERT_CAPACITY;
+        Packet {
+            bytes: [0; N],
+            len: 0,
+            _type: PhantomData,
+        }
+    }
+
+    /// Create a packet holding the encoded `value`.
+    pub fn from_value(value: &T) -> SerResult<Self>
+        where T: Serialize
+    {
+        let mut packet = Self::new();
+        packet.encode(value)?;
+        Ok(packet)
+    }
+
+    /// Create a packet from already encoded bytes, i.e. ones received from
+    /// a peer. Returns `DeError::BufferLarge` if they can't fit.
+    pub fn from_slice(bytes: &[u8]) -> DeResult<Self> {
+        let mut packet = Self::new();
+        if bytes.len() > N {
+            return Err(DeError::BufferLarge);
+        }
+        packet.bytes[..bytes.len()].copy_from_slice(bytes);
+        packet.len = bytes.len();
+        Ok(packet)
+    }
+}
+
+impl<T, const N: usize> Packet<T, N> {
+    /// Encode `value`, replacing the current contents.
+    ///
+    /// The packet is left empty if encoding fails.
+    pub fn encode(&mut self, value: &T) -> SerResult<()>
+        where T: Serialize
+    {
+        self.len = 0;
+        self.len = to_bytes(&mut self.bytes, value)?;
+        Ok(())
+    }
+
+    /// Decode the contents of the packet.
+    pub fn decode<'de>(&'de self) -> DeResult<T>
+        where T: Deserialize<'de>
+    {
+        from_bytes(self.as_bytes())
+    }
+
+    /// The encoded bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// The number of encoded bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the packet holds no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The size of the underlying buffer.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Empty the packet.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<T: MaxSize, const N: usize> Default for Packet<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Clone for Packet<T, N> {
+    fn clone(&self) -> Self {
+        Packet {
+            bytes: self.bytes,
+            len: self.len,
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<T, const N: usize> AsRef<[u8]> for Packet<T, N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for Packet<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Packet").field(&self.as_bytes()).finish()
+    }
+}
+
+#[test]
+fn test_packet() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize, MaxSize)]
+    enum Command {
+        Stop,
+        Move { x: i16, y: i16 },
+    }
+
+    let mut packet: Packet<Command, { Command::MAX_SIZE }> = Packet::new();
+    assert_eq!(packet.capacity(), 5);
+    assert!(packet.is_empty());
+
+    packet.encode(&Command::Move { x: 1, y: -1 }).unwrap();
+    assert_eq!(packet.as_bytes(), &[1, 0, 1, 0xFF, 0xFF]);
+    assert_eq!(packet.decode().unwrap(), Command::Move { x: 1, y: -1 });
+
+    packet.encode(&Command::Stop).unwrap();
+    assert_eq!(packet.len(), 1);
+    assert_eq!(packet.decode().unwrap(), Command::Stop);
+
+    let received = Packet::<Command, 5>::from_slice(&[1, 0, 2, 0, 3]).unwrap();
+    assert_eq!(received.decode().unwrap(), Command::Move { x: 2, y: 3 });
+    assert_eq!(Packet::<Command, 5>::from_slice(&[0; 6]).unwrap_err(), DeError::BufferLarge);
+}
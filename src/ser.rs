@@ -1,24 +1,448 @@
 //! serialization module
 
-use dev_prefix::*;
-use core::u8;
+use crate::dev_prefix::*;
+#[cfg(feature = "std")]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+use core::marker::PhantomData;
 
-use byteorder::{ByteOrder, BigEndian};
+#[cfg(feature = "tokio")]
+use bytes::BytesMut;
+use byteorder::{BigEndian, ByteOrder};
 use serde::ser::{self, Serialize};
 
-pub struct Serializer<'buffer> {
+use crate::error::SinkError;
+#[cfg(feature = "half")]
+use crate::float;
+#[cfg(feature = "half")]
+use crate::options::Fixint;
+use crate::options::{DefaultOptions, IntEncoding, LengthPrefix, Options, TagWidth, UsizeWidth};
+use crate::size;
+use crate::varint::{self, zigzag};
+
+/// Serializes values into an `Output`, usually a byte buffer.
+///
+/// Several values can be written back to back, e.g. a header followed by a
+/// number of records:
+///
+/// ```
+/// # extern crate ubyte;
+/// use ubyte::Serializer;
+///
+/// # fn main() {
+/// let mut buffer = [0u8; 16];
+/// let mut serializer = Serializer::new(&mut buffer);
+/// serializer.write(&2u8).unwrap();
+/// for record in &[(1u8, 0x203u16), (4, 0x506)] {
+///     serializer.write(record).unwrap();
+/// }
+/// assert_eq!(serializer.position(), 7);
+/// assert_eq!(serializer.into_written(), &[2, 1, 2, 3, 4, 5, 6]);
+/// # }
+/// ```
+pub struct Serializer<W, O = DefaultOptions> {
+    output: W,
+    options: PhantomData<O>,
+}
+
+/// Where a `Serializer` puts the encoded bytes.
+pub trait Output {
+    /// Append `bytes` to everything written so far.
+    fn write_bytes(&mut self, bytes: &[u8]) -> SerResult<()>;
+
+    /// The number of bytes written so far.
+    fn position(&self) -> usize;
+
+    /// Drop the bytes written after `position`, if the output is able to.
+    ///
+    /// Used to undo a failed `Serializer::write`.
+    fn rewind(&mut self, _position: usize) {}
+}
+
+/// Output to a fixed size byte buffer, failing with `SerError::Overflow` when
+/// it is full.
+pub struct SliceOutput<'buffer> {
     bytes: &'buffer mut [u8],
+    pos: usize,
+}
+
+impl<'buffer> SliceOutput<'buffer> {
+    /// Write to the start of `bytes`.
+    pub fn new(bytes: &'buffer mut [u8]) -> Self {
+        SliceOutput { bytes, pos: 0 }
+    }
+}
+
+impl<'buffer> Output for SliceOutput<'buffer> {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        let end = self.pos + v.len();
+        match self.bytes.get_mut(self.pos..end) {
+            Some(bytes) => bytes.copy_from_slice(v),
+            None => return Err(SerError::Overflow),
+        }
+        self.pos = end;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn rewind(&mut self, position: usize) {
+        self.pos = position;
+    }
+}
+
+/// Output that only counts the bytes, see `serialized_size`.
+#[derive(Debug, Default)]
+pub struct SizeCounter {
+    size: usize,
+}
+
+impl Output for SizeCounter {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        self.size += v.len();
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.size
+    }
+
+    fn rewind(&mut self, position: usize) {
+        self.size = position;
+    }
+}
+
+/// Output to a growable `Vec`, appending to what it already holds.
+#[cfg(feature = "std")]
+impl Output for Vec<u8> {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        self.extend_from_slice(v);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.len()
+    }
+
+    fn rewind(&mut self, position: usize) {
+        self.truncate(position);
+    }
+}
+
+/// Output to a tokio `BytesMut`, appending to what it already holds.
+#[cfg(feature = "tokio")]
+impl Output for BytesMut {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        self.extend_from_slice(v);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.len()
+    }
+
+    fn rewind(&mut self, position: usize) {
+        self.truncate(position);
+    }
+}
+
+/// Lets a serializer write to an output it doesn't own.
+impl<W: Output + ?Sized> Output for &mut W {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        (**self).write_bytes(v)
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        (**self).position()
+    }
+
+    fn rewind(&mut self, position: usize) {
+        (**self).rewind(position)
+    }
+}
+
+/// Somewhere to stream the bytes of a value to as it is encoded, e.g. a UART
+/// FIFO, so the whole message never needs to be in memory at once.
+///
+/// Bytes are passed on in small pieces: every number is a separate `write`,
+/// strings and byte slices are passed on whole. Closures taking `&[u8]` and
+/// returning a `Result` are sinks.
+pub trait ByteSink {
+    /// The error of a failed write, returned as `SinkError::Sink`.
+    type Error;
+
+    /// Take all of `bytes`, or fail.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl<F, E> ByteSink for F
+    where F: FnMut(&[u8]) -> Result<(), E>
+{
+    type Error = E;
+
+    #[inline(always)]
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        self(bytes)
+    }
+}
+
+/// Output to a `ByteSink`.
+///
+/// A failed write is reported as `SerError::Sink`, the sink's error is kept
+/// until `take_error`.
+pub struct SinkOutput<S: ByteSink> {
+    sink: S,
+    written: usize,
+    error: Option<S::Error>,
+}
+
+impl<S: ByteSink> SinkOutput<S> {
+    /// Write to `sink`.
+    pub fn new(sink: S) -> Self {
+        SinkOutput { sink, written: 0, error: None }
+    }
+
+    /// The error of the last failed write, if it hasn't been taken yet.
+    pub fn take_error(&mut self) -> Option<S::Error> {
+        self.error.take()
+    }
+
+    /// Take back the sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: ByteSink> Output for SinkOutput<S> {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        if let Err(err) = self.sink.write(v) {
+            self.error = Some(err);
+            return Err(SerError::Sink);
+        }
+        self.written += v.len();
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.written
+    }
+}
+
+/// Output to an `io::Write`. Bytes are passed on as they are encoded, so a
+/// `BufWriter` is recommended for unbuffered writers.
+#[cfg(feature = "std")]
+pub struct WriterOutput<W> {
+    writer: W,
+    written: usize,
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> WriterOutput<W> {
+    /// Write to `writer`.
+    pub fn new(writer: W) -> Self {
+        WriterOutput { writer, written: 0 }
+    }
+
+    /// Take back the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> Output for WriterOutput<W> {
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8]) -> SerResult<()> {
+        self.writer.write_all(v)?;
+        self.written += v.len();
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn position(&self) -> usize {
+        self.written
+    }
 }
 
 /// serialize the value in the buffer and return the length
 /// of the buffer used.
 pub fn to_bytes<T>(bytes: &mut [u8], value: &T) -> SerResult<usize>
-    where T: Serialize
+    where T: Serialize + ?Sized
+{
+    to_bytes_with(DefaultOptions::new(), bytes, value)
+}
+
+/// serialize the value in the buffer using the encoding `options` and return
+/// the length of the buffer used.
+pub fn to_bytes_with<O, T>(options: O, bytes: &mut [u8], value: &T) -> SerResult<usize>
+    where O: Options,
+          T: Serialize + ?Sized
+{
+    Serializer::new_with(options, bytes).write(value)
+}
+
+/// The number of bytes `to_bytes` would use for the value, without writing
+/// anything.
+///
+/// Fails the same way `to_bytes` does, except there is no `Overflow`.
+pub fn serialized_size<T>(value: &T) -> SerResult<usize>
+    where T: Serialize + ?Sized
+{
+    serialized_size_with(DefaultOptions::new(), value)
+}
+
+/// Same as `serialized_size` but using the encoding `options`.
+pub fn serialized_size_with<O, T>(options: O, value: &T) -> SerResult<usize>
+    where O: Options,
+          T: Serialize + ?Sized
+{
+    Serializer::with_output(options, SizeCounter::default()).write(value)
+}
+
+/// serialize the value into a new `Vec`.
+#[cfg(feature = "std")]
+pub fn to_vec<T>(value: &T) -> SerResult<Vec<u8>>
+    where T: Serialize + ?Sized
+{
+    to_vec_with(DefaultOptions::new(), value)
+}
+
+/// Same as `to_vec` but using the encoding `options`.
+#[cfg(feature = "std")]
+pub fn to_vec_with<O, T>(options: O, value: &T) -> SerResult<Vec<u8>>
+    where O: Options,
+          T: Serialize + ?Sized
+{
+    let mut serializer = Serializer::with_output(options, Vec::new());
+    serializer.write(value)?;
+    Ok(serializer.into_output())
+}
+
+/// serialize the value into `writer` and return the number of bytes written.
+///
+/// If it fails part of the value may already have been written.
+#[cfg(feature = "std")]
+pub fn to_writer<W, T>(writer: W, value: &T) -> SerResult<usize>
+    where W: io::Write,
+          T: Serialize + ?Sized
+{
+    to_writer_with(DefaultOptions::new(), writer, value)
+}
+
+/// Same as `to_writer` but using the encoding `options`.
+#[cfg(feature = "std")]
+pub fn to_writer_with<O, W, T>(options: O, writer: W, value: &T) -> SerResult<usize>
+    where O: Options,
+          W: io::Write,
+          T: Serialize + ?Sized
 {
-    let initial = bytes.as_ptr() as usize;
-    let mut serializer = Serializer { bytes: bytes };
-    value.serialize(&mut serializer)?;
-    Ok(serializer.bytes.as_ptr() as usize - initial)
+    Serializer::with_output(options, WriterOutput::new(writer)).write(value)
+}
+
+/// serialize the value to `sink` as it is encoded and return the number of
+/// bytes written.
+///
+/// If it fails part of the value may already have been written. A failed
+/// write returns the error of the sink as `SinkError::Sink`.
+pub fn to_sink<S, T>(sink: S, value: &T) -> Result<usize, SinkError<S::Error>>
+    where S: ByteSink,
+          T: Serialize + ?Sized
+{
+    to_sink_with(DefaultOptions::new(), sink, value)
+}
+
+/// Same as `to_sink` but using the encoding `options`.
+pub fn to_sink_with<O, S, T>(options: O, sink: S, value: &T)
+    -> Result<usize, SinkError<S::Error>>
+    where O: Options,
+          S: ByteSink,
+          T: Serialize + ?Sized
+{
+    let mut serializer = Serializer::with_output(options, SinkOutput::new(sink));
+    serializer.write(value).map_err(|err| match serializer.output.take_error() {
+        Some(err) => SinkError::Sink(err),
+        None => SinkError::Ser(err),
+    })
+}
+
+impl<'buffer> Serializer<SliceOutput<'buffer>> {
+    /// Create a serializer writing to the start of `bytes`.
+    pub fn new(bytes: &'buffer mut [u8]) -> Self {
+        Serializer::new_with(DefaultOptions::new(), bytes)
+    }
+}
+
+impl<'buffer, O: Options> Serializer<SliceOutput<'buffer>, O> {
+    /// Create a serializer writing to the start of `bytes` using the encoding
+    /// `options`.
+    pub fn new_with(options: O, bytes: &'buffer mut [u8]) -> Self {
+        Serializer::with_output(options, SliceOutput::new(bytes))
+    }
+
+    /// The number of bytes left in the buffer.
+    pub fn remaining(&self) -> usize {
+        self.output.bytes.len() - self.output.pos
+    }
+
+    /// The part of the buffer that has been written to.
+    pub fn into_written(self) -> &'buffer mut [u8] {
+        &mut self.output.bytes[..self.output.pos]
+    }
+}
+
+impl<W: Output, O: Options> Serializer<W, O> {
+    /// Create a serializer writing to `output` using the encoding `options`.
+    pub fn with_output(_options: O, output: W) -> Self {
+        Serializer { output, options: PhantomData }
+    }
+
+    /// Serialize `value` after everything written so far and return the number
+    /// of bytes it took up.
+    ///
+    /// If it fails the output is rewound to where it was, so the serializer can
+    /// still be used (e.g. to write a smaller value).
+    pub fn write<T>(&mut self, value: &T) -> SerResult<usize>
+        where T: Serialize + ?Sized
+    {
+        let start = self.output.position();
+        match value.serialize(&mut *self) {
+            Ok(()) => Ok(self.output.position() - start),
+            Err(err) => {
+                self.output.rewind(start);
+                Err(err)
+            }
+        }
+    }
+
+    /// The number of bytes written so far.
+    pub fn position(&self) -> usize {
+        self.output.position()
+    }
+
+    /// The output being written to.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Take back the output.
+    pub fn into_output(self) -> W {
+        self.output
+    }
 }
 
 
@@ -26,61 +450,87 @@ macro_rules! impl_value {
     ($ty:ty, $ser_method:ident, $bo_method:ident) => {
         #[inline(always)]
         fn $ser_method(self, value: $ty) -> SerResult<()> {
-            self.assert_enough::<$ty>()?;
-            BigEndian::$bo_method(&mut self.bytes, value);
-            self.consume::<$ty>();
-            Ok(())
+            let mut buffer = [0u8; mem::size_of::<$ty>()];
+            O::Endian::$bo_method(&mut buffer, value);
+            self.output.write_bytes(&buffer)
         }
     }
 }
 
-macro_rules! impl_not_supported {
-    ($ty:ty, $ser_method:ident) => {
+/// integers wider than a byte, `$varint` converts them to the unsigned value
+/// that is varint encoded
+macro_rules! impl_int {
+    ($ty:ty, $ser_method:ident, $bo_method:ident, $varint:expr) => {
         #[inline(always)]
-        fn $ser_method(self, _value: $ty) -> SerResult<()> {
-            unimplemented!()
+        fn $ser_method(self, value: $ty) -> SerResult<()> {
+            if O::Ints::VARINT {
+                let varint: fn($ty) -> u64 = $varint;
+                return self.write_varint(varint(value));
+            }
+            let mut buffer = [0u8; mem::size_of::<$ty>()];
+            O::Endian::$bo_method(&mut buffer, value);
+            self.output.write_bytes(&buffer)
         }
     }
 }
 
-impl<'buffer> Serializer<'buffer> {
-    /// Consume some of the buffer.
-    /// this should NEVER fail (the buffer should always be checked first)
+impl<W: Output, O: Options> Serializer<W, O> {
     #[inline(always)]
-    fn consume<T>(&mut self) {
-        let num = mem::size_of::<T>();
-        // FIXME: WHY CAN'T I DO THIS???
-        //self.bytes = &mut self.bytes[num..];
-        let mut ptr = self.bytes.as_mut_ptr();
-        unsafe {
-            ptr = ptr.offset(num as isize);
-            self.bytes = slice::from_raw_parts_mut(ptr, self.bytes.len() - num);
+    fn write_variant(&mut self, index: u32) -> SerResult<()> {
+        if index > O::Tag::MAX {
+            return Err(SerError::EnumLarge);
         }
+        O::Tag::serialize(index, &mut *self)
     }
 
-    /// make sure there is enough buffer left
+    /// write a `Usize` (or the bits of an `Isize`), `O::Usize` wide
     #[inline(always)]
-    fn assert_enough<T>(&self) -> SerResult<()> {
-        if mem::size_of::<T>() > self.bytes.len() {
-            Err(SerError::Overflow)
+    fn write_size(&mut self, v: u64, signed: bool) -> SerResult<()> {
+        let fits = if signed {
+            O::Usize::fits_signed(v as i64)
         } else {
-            Ok(())
+            O::Usize::fits_unsigned(v)
+        };
+        if !fits {
+            return Err(SerError::IntLarge);
+        }
+        if O::Ints::VARINT {
+            return self.write_varint(if signed { zigzag(v as i64) } else { v });
         }
+        let width = O::Usize::MAX_SIZE;
+        let mut bytes = [0u8; 8];
+        O::Endian::write_uint(&mut bytes[..width], v & (u64::MAX >> (64 - 8 * width)), width);
+        self.output.write_bytes(&bytes[..width])
     }
 
     #[inline(always)]
-    fn write_variant(&mut self, index: u32) -> SerResult<()> {
-        self.assert_enough::<u8>()?;
-        if index > u8::MAX as u32 {
-            panic!(MSG_ENUM_LARGE);
+    fn write_varint(&mut self, v: u64) -> SerResult<()> {
+        let mut buffer = [0u8; varint::MAX_LEN];
+        let len = varint::encode(v, &mut buffer);
+        self.output.write_bytes(&buffer[..len])
+    }
+
+    /// write the length prefix of a string, byte slice or sequence
+    #[inline(always)]
+    fn write_len(&mut self, len: usize, kind: Unsupported) -> SerResult<()> {
+        if !O::Length::ENABLED {
+            return Err(SerError::Unsupported(kind));
         }
-        self.bytes[0] = index as u8;
-        self.consume::<u8>();
-        Ok(())
+        if len > O::MAX_LEN {
+            return Err(SerError::LengthLarge);
+        }
+        O::Length::serialize(len, &mut *self)
+    }
+
+    /// write a string or byte slice
+    #[inline(always)]
+    fn write_bytes(&mut self, v: &[u8], kind: Unsupported) -> SerResult<()> {
+        self.write_len(v.len(), kind)?;
+        self.output.write_bytes(v)
     }
 }
 
-impl<'a, 'buffer: 'a> ser::Serializer for &'a mut Serializer<'buffer> {
+impl<W: Output, O: Options> ser::Serializer for &mut Serializer<W, O> {
     type Ok = (); // outputs data into buffer
     type Error = SerError;
 
@@ -96,61 +546,81 @@ impl<'a, 'buffer: 'a> ser::Serializer for &'a mut Serializer<'buffer> {
 
     #[inline(always)]
     fn serialize_bool(self, v: bool) -> SerResult<()> {
-        self.assert_enough::<u8>()?;
-        self.bytes[0] = v as u8;
-        self.consume::<u8>();
-        Ok(())
+        self.output.write_bytes(&[v as u8])
     }
 
     #[inline(always)]
     fn serialize_u8(self, v: u8) -> SerResult<()> {
-        self.assert_enough::<u8>()?;
-        self.bytes[0] = v;
-        self.consume::<u8>();
-        Ok(())
+        self.output.write_bytes(&[v])
     }
 
     #[inline(always)]
     fn serialize_i8(self, v: i8) -> SerResult<()> {
-        self.assert_enough::<i8>()?;
-        self.bytes[0] = v as u8;
-        self.consume::<i8>();
-        Ok(())
+        self.output.write_bytes(&[v as u8])
     }
 
     // numbers
-    impl_value!(u16, serialize_u16, write_u16);
-    impl_value!(i16, serialize_i16, write_i16);
-    impl_value!(u32, serialize_u32, write_u32);
-    impl_value!(i32, serialize_i32, write_i32);
-    impl_value!(u64, serialize_u64, write_u64);
-    impl_value!(i64, serialize_i64, write_i64);
+    impl_int!(u16, serialize_u16, write_u16, |v| v as u64);
+    impl_int!(i16, serialize_i16, write_i16, |v| zigzag(v as i64));
+    impl_int!(u32, serialize_u32, write_u32, |v| v as u64);
+    impl_int!(i32, serialize_i32, write_i32, |v| zigzag(v as i64));
+    impl_int!(u64, serialize_u64, write_u64, |v| v);
+    impl_int!(i64, serialize_i64, write_i64, zigzag);
+
+    // always fixed width, even with varint encoding
+    impl_value!(u128, serialize_u128, write_u128);
+    impl_value!(i128, serialize_i128, write_i128);
 
     impl_value!(f32, serialize_f32, write_f32);
     impl_value!(f64, serialize_f64, write_f64);
 
-    // not supported
-    impl_not_supported!(char, serialize_char);
-    impl_not_supported!(&str, serialize_str);
-    impl_not_supported!(&[u8], serialize_bytes);
+    // UTF-8, 1 to 4 bytes
+    #[inline(always)]
+    fn serialize_char(self, v: char) -> SerResult<()> {
+        let mut buffer = [0u8; 4];
+        self.output.write_bytes(v.encode_utf8(&mut buffer).as_bytes())
+    }
+
+    // length prefixed, only when enabled in the options
+
+    #[inline(always)]
+    fn serialize_str(self, v: &str) -> SerResult<()> {
+        self.write_bytes(v.as_bytes(), Unsupported::Str)
+    }
+
+    #[inline(always)]
+    fn serialize_bytes(self, v: &[u8]) -> SerResult<()> {
+        self.write_bytes(v, Unsupported::Bytes)
+    }
+
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn collect_str<T>(self, value: &T) -> SerResult<()>
+        where T: ?Sized + fmt::Display
+    {
+        self.serialize_str(&value.to_string())
+    }
 
+    #[cfg(not(feature = "std"))]
     #[inline(always)]
     fn collect_str<T: ?Sized>(self, _value: &T) -> SerResult<()> {
-        unimplemented!()
+        Err(SerError::Unsupported(Unsupported::Str))
     }
 
     // enums with values
 
+    // the tag of `Option` is always a single byte, whatever the options
+
     #[inline(always)]
     fn serialize_none(self) -> SerResult<()> {
-        self.write_variant(0)
+        self.serialize_bool(false)
     }
 
     #[inline(always)]
     fn serialize_some<T>(self, value: &T) -> SerResult<()>
         where T: ?Sized + Serialize
     {
-        self.write_variant(1)?;
+        self.serialize_bool(true)?;
         value.serialize(self)
     }
 
@@ -175,9 +645,25 @@ impl<'a, 'buffer: 'a> ser::Serializer for &'a mut Serializer<'buffer> {
 
     // nested struct
     #[inline(always)]
-    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> SerResult<()>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> SerResult<()>
         where T: ?Sized + Serialize
     {
+        if name == size::USIZE_NAME || name == size::ISIZE_NAME {
+            // a `Usize`/`Isize` hands over a `u64`/`i64`, narrow it to
+            // `O::Usize`
+            let mut wide = [0u8; 8];
+            to_bytes(&mut wide, value)?;
+            return self.write_size(BigEndian::read_u64(&wide), name == size::ISIZE_NAME);
+        }
+        #[cfg(feature = "half")]
+        if name == float::NAME {
+            // the bits of an `F16` are written like a float, never a varint
+            let mut raw = Serializer::<_, Fixint<O>> {
+                output: &mut self.output,
+                options: PhantomData,
+            };
+            return value.serialize(&mut raw);
+        }
         value.serialize(self)
     }
 
@@ -197,8 +683,13 @@ impl<'a, 'buffer: 'a> ser::Serializer for &'a mut Serializer<'buffer> {
 
     // Compound Types: only some supported
     #[inline(always)]
-    fn serialize_seq(self, _len: Option<usize>) -> SerResult<Self::SerializeSeq> {
-        unimplemented!()
+    fn serialize_seq(self, len: Option<usize>) -> SerResult<Self::SerializeSeq> {
+        match len {
+            Some(len) => self.write_len(len, Unsupported::Seq)?,
+            None if O::Length::ENABLED => return Err(SerError::LengthUnknown),
+            None => return Err(SerError::Unsupported(Unsupported::Seq)),
+        }
+        Ok(self)
     }
 
     #[inline(always)]
@@ -227,7 +718,7 @@ impl<'a, 'buffer: 'a> ser::Serializer for &'a mut Serializer<'buffer> {
 
     #[inline(always)]
     fn serialize_map(self, _len: Option<usize>) -> SerResult<Self::SerializeMap> {
-        unimplemented!()
+        Err(SerError::Unsupported(Unsupported::Map))
     }
 
     #[inline(always)]
@@ -251,7 +742,7 @@ impl<'a, 'buffer: 'a> ser::Serializer for &'a mut Serializer<'buffer> {
 
 macro_rules! impl_field {
     ($trait:path) => {
-        impl<'a, 'buffer: 'a> $trait for &'a mut Serializer<'buffer> {
+        impl<W: Output, O: Options> $trait for &mut Serializer<W, O> {
             type Ok = ();
             type Error = SerError;
 
@@ -270,7 +761,7 @@ macro_rules! impl_field {
 
 macro_rules! impl_key_field {
     ($trait:path) => {
-        impl<'a, 'buffer: 'a> $trait for &'a mut Serializer<'buffer> {
+        impl<W: Output, O: Options> $trait for &mut Serializer<W, O> {
             type Ok = ();
             type Error = SerError;
 
@@ -292,13 +783,13 @@ impl_field!(ser::SerializeTupleVariant);
 impl_key_field!(ser::SerializeStruct);
 impl_key_field!(ser::SerializeStructVariant);
 
-impl<'a, 'buffer: 'a> ser::SerializeTuple for &'a mut Serializer<'buffer> {
+impl<W: Output, O: Options> ser::SerializeTuple for &mut Serializer<W, O> {
     type Ok = ();
     type Error = SerError;
 
     #[inline]
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> SerResult<()>
-        where T: Serialize
+    fn serialize_element<T>(&mut self, value: &T) -> SerResult<()>
+        where T: ?Sized + Serialize
     {
         value.serialize(&mut **self)
     }
@@ -309,26 +800,26 @@ impl<'a, 'buffer: 'a> ser::SerializeTuple for &'a mut Serializer<'buffer> {
     }
 }
 
-// seq with unknown length not supported
-impl<'a, 'buffer: 'a> ser::SerializeSeq for &'a mut Serializer<'buffer> {
+// the length prefix is written by `serialize_seq`
+impl<W: Output, O: Options> ser::SerializeSeq for &mut Serializer<W, O> {
     type Ok = ();
     type Error = SerError;
 
     #[inline]
-    fn serialize_element<T: ?Sized>(&mut self, _value: &T) -> SerResult<()>
-        where T: Serialize
+    fn serialize_element<T>(&mut self, value: &T) -> SerResult<()>
+        where T: ?Sized + Serialize
     {
-        unreachable!()
+        value.serialize(&mut **self)
     }
 
     #[inline]
     fn end(self) -> SerResult<()> {
-        unreachable!()
+        Ok(())
     }
 }
 
-// map not supported
-impl<'a, 'buffer: 'a> ser::SerializeMap for &'a mut Serializer<'buffer> {
+// map not supported: `serialize_map` always errors
+impl<W: Output, O: Options> ser::SerializeMap for &mut Serializer<W, O> {
     type Ok = ();
     type Error = SerError;
 
@@ -397,3 +888,458 @@ fn test_ser_enum() {
     assert_eq!(len, 5);
     assert_eq!(&expected, &buffer[..len]);
 }
+
+#[test]
+fn test_ser_custom() {
+    use crate::error::Message;
+
+    struct Odd;
+
+    impl Serialize for Odd {
+        fn serialize<S: ser::Serializer>(&self, _s: S) -> Result<S::Ok, S::Error> {
+            Err(ser::Error::custom("not today"))
+        }
+    }
+
+    let mut buffer = [0u8; 4];
+    assert_eq!(to_bytes(&mut buffer, &(1u8, Odd)).unwrap_err(),
+               SerError::Custom(Message::new("not today")));
+}
+
+#[test]
+fn test_ser_unsupported() {
+    use std::collections::BTreeMap;
+    use serde::ser::Serializer as _;
+
+    fn unsupported<T: Serialize>(value: &T) -> SerError {
+        let mut buffer = [0u8; 16];
+        to_bytes(&mut buffer, value).unwrap_err()
+    }
+
+    assert_eq!(unsupported(&"abc"), SerError::Unsupported(Unsupported::Str));
+    assert_eq!(unsupported(&::serde_bytes::Bytes::new(b"abc")),
+               SerError::Unsupported(Unsupported::Bytes));
+    assert_eq!(unsupported(&&[1u8, 2][..]), SerError::Unsupported(Unsupported::Seq));
+    assert_eq!(unsupported(&BTreeMap::<u8, u8>::new()), SerError::Unsupported(Unsupported::Map));
+
+    let mut buffer = [0u8; 16];
+    let mut serializer = Serializer::new(&mut buffer);
+    assert_eq!((&mut serializer).collect_str(&1).unwrap_err(),
+               SerError::Unsupported(Unsupported::Str));
+}
+
+#[test]
+fn test_ser_length_prefixed() {
+    use crate::options::{Config, U8, U16};
+
+    let mut buffer = [0u8; 16];
+
+    let options = Config::new().with_length::<U8, 4>();
+    let len = to_bytes_with(options, &mut buffer, &"abc").unwrap();
+    assert_eq!(&buffer[..len], &[3, b'a', b'b', b'c']);
+    let len = to_bytes_with(options, &mut buffer, &::serde_bytes::Bytes::new(&[9, 8])).unwrap();
+    assert_eq!(&buffer[..len], &[2, 9, 8]);
+    let len = to_bytes_with(options, &mut buffer, &[1u16, 2][..]).unwrap();
+    assert_eq!(&buffer[..len], &[2, 0, 1, 0, 2]);
+
+    assert_eq!(to_bytes_with(options, &mut buffer, &"abcde").unwrap_err(), SerError::LengthLarge);
+    assert_eq!(to_bytes_with(options, &mut buffer[..3], &"abc").unwrap_err(), SerError::Overflow);
+
+    struct Unknown;
+    impl Serialize for Unknown {
+        fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            ser::SerializeSeq::end(s.serialize_seq(None)?)
+        }
+    }
+    assert_eq!(to_bytes_with(options, &mut buffer, &Unknown).unwrap_err(), SerError::LengthUnknown);
+
+    let options = Config::new().with_length::<U16, 1000>();
+    let len = to_bytes_with(options, &mut buffer, &(1u8, "hi")).unwrap();
+    assert_eq!(&buffer[..len], &[1, 0, 2, b'h', b'i']);
+}
+
+#[test]
+fn test_ser_options() {
+    use crate::options::{Config, U16, U32};
+
+    #[derive(Serialize)]
+    enum E {
+        A,
+        B(u16),
+    }
+
+    let mut buffer = [0u8; 16];
+
+    let options = Config::new().with_little_endian();
+    let len = to_bytes_with(options, &mut buffer, &(0x0102u16, -2i32, 1.0f32)).unwrap();
+    assert_eq!(&buffer[..len], &[2, 1, 0xFE, 0xFF, 0xFF, 0xFF, 0, 0, 0x80, 0x3F]);
+
+    let options = Config::new().with_tag::<U16>();
+    let len = to_bytes_with(options, &mut buffer, &(E::A, E::B(3), Some(4u8))).unwrap();
+    assert_eq!(&buffer[..len], &[0, 0, 0, 1, 0, 3, 1, 4]);
+
+    let options = Config::new().with_little_endian().with_tag::<U32>();
+    let len = to_bytes_with(options, &mut buffer, &E::B(3)).unwrap();
+    assert_eq!(&buffer[..len], &[1, 0, 0, 0, 3, 0]);
+    assert_eq!(to_bytes_with(options, &mut buffer[..3], &E::B(3)).unwrap_err(), SerError::Overflow);
+}
+
+#[test]
+fn test_ser_varint() {
+    use crate::options::{Config, U16};
+
+    #[derive(Serialize)]
+    enum E {
+        A(u8, u16, i32, u64),
+        B(&'static str),
+    }
+
+    let mut buffer = [0u8; 32];
+
+    let options = Config::new().with_varint_encoding().with_little_endian();
+    let len = to_bytes_with(options, &mut buffer, &(1u16, 300u32, -1i64, 1.0f32)).unwrap();
+    assert_eq!(&buffer[..len], &[1, 0xAC, 0x02, 1, 0, 0, 0x80, 0x3F]);
+
+    let options = options.with_tag::<U16>().with_length::<U16, 200>();
+    let len = to_bytes_with(options, &mut buffer, &E::A(0xFF, 0xFF, -65, u64::MAX)).unwrap();
+    assert_eq!(&buffer[..len], &[0, 0xFF, 0xFF, 0x01, 0x81, 0x01,
+                                 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+    let len = to_bytes_with(options, &mut buffer, &E::B("hi")).unwrap();
+    assert_eq!(&buffer[..len], &[1, 2, b'h', b'i']);
+
+    assert_eq!(to_bytes_with(options, &mut buffer[..1], &300u16).unwrap_err(), SerError::Overflow);
+}
+
+#[test]
+fn test_ser_wide_and_char() {
+    use crate::options::Config;
+
+    let mut buffer = [0u8; 40];
+
+    let value = (u128::MAX - 1, -2i128);
+    let len = to_bytes(&mut buffer, &value).unwrap();
+    assert_eq!(len, 32);
+    assert_eq!(&buffer[..16], &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                                0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE]);
+    assert_eq!(&buffer[16..32], &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                                  0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE]);
+    let options = Config::new().with_little_endian().with_varint_encoding();
+    let len = to_bytes_with(options, &mut buffer, &1u128).unwrap();
+    assert_eq!(&buffer[..len], &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    let len = to_bytes(&mut buffer, &('A', '\u{e9}', '\u{20ac}', '\u{1f980}')).unwrap();
+    assert_eq!(&buffer[..len], "A\u{e9}\u{20ac}\u{1f980}".as_bytes());
+    assert_eq!(len, 1 + 2 + 3 + 4);
+    assert_eq!(to_bytes(&mut buffer[..3], &'\u{1f980}').unwrap_err(), SerError::Overflow);
+}
+
+#[test]
+fn test_ser_enum_large() {
+    use crate::options::{Config, U16, VarintTag};
+
+    struct Variant(u32);
+    impl Serialize for Variant {
+        fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_unit_variant("Command", self.0, "")
+        }
+    }
+
+    let mut buffer = [0u8; 8];
+    assert_eq!(to_bytes(&mut buffer, &Variant(255)).unwrap(), 1);
+    assert_eq!(to_bytes(&mut buffer, &Variant(256)).unwrap_err(), SerError::EnumLarge);
+
+    let options = Config::new().with_tag::<U16>();
+    let len = to_bytes_with(options, &mut buffer, &Variant(400)).unwrap();
+    assert_eq!(&buffer[..len], &[0x01, 0x90]);
+    assert_eq!(to_bytes_with(options, &mut buffer, &Variant(0x10000)).unwrap_err(),
+               SerError::EnumLarge);
+
+    let options = Config::new().with_tag::<VarintTag>();
+    let len = to_bytes_with(options, &mut buffer, &Variant(3)).unwrap();
+    assert_eq!(&buffer[..len], &[3]);
+    let len = to_bytes_with(options, &mut buffer, &Variant(400)).unwrap();
+    assert_eq!(&buffer[..len], &[0x90, 0x03]);
+    let len = to_bytes_with(options, &mut buffer, &Variant(u32::MAX)).unwrap();
+    assert_eq!(&buffer[..len], &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+}
+
+/// Every supported type, serialized into every buffer that is too short, must
+/// fail with `Overflow` and round trip when the buffer is large enough.
+#[test]
+fn test_ser_all_types() {
+    use crate::de::from_bytes_with;
+    use crate::options::{Config, U8, U16, VarintTag};
+    use serde::de::DeserializeOwned;
+    use crate::varint::Varint;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum E {
+        Unit,
+        Newtype(i64),
+        Tuple(u8, Option<u16>),
+        Struct { a: bool, b: char },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Unit;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Newtype(u32);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct All {
+        ints: (u8, u16, u32, u64, usize, u128),
+        signed: (i8, i16, i32, i64, isize, i128),
+        floats: (f32, f64),
+        misc: (bool, char, (), Unit, Newtype),
+        array: [i16; 3],
+        option: (Option<u8>, Option<E>),
+        enums: [E; 4],
+        varints: (Varint<u64>, Varint<i32>),
+        string: String,
+        seq: Vec<E>,
+    }
+
+    fn check<O: Options, T>(options: O, value: &T)
+        where T: Serialize + DeserializeOwned + PartialEq + ::std::fmt::Debug
+    {
+        let mut buffer = [0u8; 512];
+        let len = to_bytes_with(options, &mut buffer, value).unwrap();
+        assert_eq!(&from_bytes_with::<_, T>(options, &buffer[..len]).unwrap(), value);
+        assert_eq!(to_bytes_with(options, &mut buffer[..len], value).unwrap(), len);
+        for short in 0..len {
+            assert_eq!(to_bytes_with(options, &mut buffer[..short], value).unwrap_err(),
+                       SerError::Overflow);
+        }
+    }
+
+    let value = All {
+        ints: (1, 0x203, u32::MAX, 1 << 40, 7, u128::MAX - 3),
+        signed: (-1, -300, i32::MIN, -(1 << 40), -7, i128::MIN),
+        floats: (-1.5, 3e100),
+        misc: (true, '\u{1f980}', (), Unit, Newtype(0xDEAD)),
+        array: [-1, 0, 1],
+        option: (Some(3), None),
+        enums: [E::Unit, E::Newtype(-2), E::Tuple(4, Some(500)),
+                E::Struct { a: false, b: 'x' }],
+        varints: (Varint(u64::MAX), Varint(-65)),
+        string: "h\u{e9}llo".to_string(),
+        seq: vec![E::Unit, E::Tuple(1, None)],
+    };
+
+    check(Config::new().with_length::<U8, 16>(), &value);
+    check(Config::new().with_little_endian().with_tag::<U16>().with_length::<U16, 300>(),
+          &value);
+    check(Config::new().with_varint_encoding().with_tag::<VarintTag>().with_length::<U8, 16>(),
+          &value);
+    // without a length prefix
+    check(Config::new(), &(value.ints, value.signed, value.floats, value.misc, value.enums));
+}
+
+#[test]
+fn test_ser_serializer() {
+    use crate::options::{Config, U8};
+
+    let mut buffer = [0u8; 8];
+    let mut serializer = Serializer::new(&mut buffer);
+    assert_eq!(serializer.position(), 0);
+    assert_eq!(serializer.remaining(), 8);
+    assert_eq!(serializer.write(&0x0102u16).unwrap(), 2);
+    assert_eq!(serializer.write(&(3u8, 4u8)).unwrap(), 2);
+    assert_eq!(serializer.position(), 4);
+    assert_eq!(serializer.remaining(), 4);
+
+    // a failed write leaves the position untouched
+    assert_eq!(serializer.write(&(5u8, 6u64)).unwrap_err(), SerError::Overflow);
+    assert_eq!(serializer.position(), 4);
+    assert_eq!(serializer.write(&0x05060708u32).unwrap(), 4);
+    assert_eq!(serializer.remaining(), 0);
+    assert_eq!(serializer.write(&0u8).unwrap_err(), SerError::Overflow);
+    assert_eq!(serializer.into_written(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+    let options = Config::new().with_little_endian().with_length::<U8, 8>();
+    let mut buffer = [0u8; 8];
+    let mut serializer = Serializer::new_with(options, &mut buffer);
+    serializer.write("ab").unwrap();
+    serializer.write(&0x0304u16).unwrap();
+    let written = serializer.into_written();
+    assert_eq!(written, &[2, b'a', b'b', 4, 3]);
+}
+
+#[test]
+fn test_ser_serialized_size() {
+    use crate::options::{Config, U16, U8};
+
+    assert_eq!(serialized_size(&()).unwrap(), 0);
+    assert_eq!(serialized_size(&(1u8, 2u32, Some('\u{e9}'))).unwrap(), 1 + 4 + 1 + 2);
+    assert_eq!(serialized_size("abc").unwrap_err(), SerError::Unsupported(Unsupported::Str));
+
+    let options = Config::new().with_length::<U8, 4>().with_varint_encoding();
+    assert_eq!(serialized_size_with(options, &(300u16, "abcd")).unwrap(), 2 + 1 + 4);
+    assert_eq!(serialized_size_with(options, "abcde").unwrap_err(), SerError::LengthLarge);
+    let options = Config::new().with_usize::<U16>();
+    assert_eq!(serialized_size_with(options, &crate::size::Usize(1)).unwrap(), 2);
+
+    // the same checks as `to_bytes`
+    struct Variant(u32);
+    impl Serialize for Variant {
+        fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_unit_variant("Command", self.0, "")
+        }
+    }
+    assert_eq!(serialized_size(&Variant(255)).unwrap(), 1);
+    assert_eq!(serialized_size(&Variant(256)).unwrap_err(), SerError::EnumLarge);
+
+    let mut serializer = Serializer::with_output(Config::new(), SizeCounter::default());
+    serializer.write(&1u16).unwrap();
+    assert_eq!(serializer.write(&Variant(256)).unwrap_err(), SerError::EnumLarge);
+    serializer.write(&Variant(3)).unwrap();
+    assert_eq!(serializer.position(), 3);
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_ser_serialized_size_matches(
+        ints in proptest::prelude::any::<(u8, i16, u32, i64, u128, bool)>(),
+        floats in proptest::prelude::any::<(f32, f64, char)>(),
+        option in proptest::prelude::any::<Option<(u16, i8)>>(),
+        string in ".{0,12}",
+        seq in proptest::collection::vec(proptest::prelude::any::<Option<i32>>(), 0..12),
+        tag in 0u32..300,
+    ) {
+        use crate::options::{Config, U16, U8, VarintTag};
+
+        #[derive(Serialize)]
+        struct Value<'a> {
+            ints: (u8, i16, u32, i64, u128, bool),
+            floats: (f32, f64, char),
+            option: Option<(u16, i8)>,
+            string: &'a str,
+            seq: &'a [Option<i32>],
+        }
+
+        struct Variant(u32, u16);
+        impl Serialize for Variant {
+            fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_newtype_variant("Variant", self.0, "", &self.1)
+            }
+        }
+
+        fn check<O: Options, T: Serialize>(options: O, value: &T) {
+            let mut buffer = [0u8; 512];
+            let size = serialized_size_with(options, value);
+            let written = to_bytes_with(options, &mut buffer, value);
+            assert_eq!(size, written);
+            if let Ok(size) = size {
+                assert_eq!(to_bytes_with(options, &mut buffer[..size], value), Ok(size));
+            }
+        }
+
+        let value = Value { ints, floats, option, string: &string, seq: &seq };
+        let variant = (Variant(tag, ints.1 as u16), value.ints);
+        check(Config::new(), &value);
+        check(Config::new(), &variant);
+        check(Config::new().with_length::<U8, 32>(), &value);
+        check(Config::new().with_little_endian().with_tag::<U16>().with_length::<U16, 10>(),
+              &(&value, &variant));
+        check(Config::new().with_varint_encoding().with_tag::<VarintTag>()
+                  .with_length::<U8, 12>(),
+              &(&value, &variant));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_ser_to_vec_and_writer() {
+    use crate::options::{Config, U8};
+
+    #[derive(Serialize)]
+    struct Record<'a> {
+        id: u16,
+        name: &'a str,
+    }
+
+    let options = Config::new().with_length::<U8, 16>();
+    let record = Record { id: 0x102, name: "abc" };
+    let bytes = to_vec_with(options, &record).unwrap();
+    assert_eq!(bytes, vec![1, 2, 3, b'a', b'b', b'c']);
+    assert_eq!(to_vec(&(1u8, -1i16)).unwrap(), vec![1, 0xFF, 0xFF]);
+    assert_eq!(to_vec(&"abc").unwrap_err(), SerError::Unsupported(Unsupported::Str));
+
+    // a failed write is dropped from the vec
+    let mut serializer = Serializer::with_output(options, vec![9]);
+    serializer.write(&7u8).unwrap();
+    assert_eq!(serializer.write(&(1u8, "too long for the max len")).unwrap_err(),
+               SerError::LengthLarge);
+    assert_eq!(serializer.into_output(), vec![9, 7]);
+
+    let mut writer = Vec::new();
+    assert_eq!(to_writer_with(options, &mut writer, &record).unwrap(), 6);
+    assert_eq!(to_writer(&mut writer, &0x0304u16).unwrap(), 2);
+    assert_eq!(writer, vec![1, 2, 3, b'a', b'b', b'c', 3, 4]);
+
+    let mut buffer = [0u8; 3];
+    assert_eq!(to_writer(&mut buffer[..], &(1u16, 2u16)).unwrap_err(),
+               SerError::Io(io::ErrorKind::WriteZero));
+}
+
+#[test]
+fn test_ser_sink() {
+    use crate::options::{Config, U16};
+
+    // a calibration table much larger than the FIFO it is streamed through
+    let mut table = [[0u16; 32]; 32];
+    for (i, row) in table.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (i * 32 + j) as u16;
+        }
+    }
+    let mut expected = [0u8; 2048];
+    assert_eq!(to_bytes(&mut expected, &table).unwrap(), 2048);
+
+    let mut fifo = [0u8; 64];
+    let mut fill = 0;
+    let mut sent = Vec::new();
+    let mut largest = 0;
+    let len = to_sink(|bytes: &[u8]| -> Result<(), ()> {
+        largest = largest.max(bytes.len());
+        for &byte in bytes {
+            if fill == fifo.len() {
+                sent.extend_from_slice(&fifo);
+                fill = 0;
+            }
+            fifo[fill] = byte;
+            fill += 1;
+        }
+        Ok(())
+    }, &table).unwrap();
+    sent.extend_from_slice(&fifo[..fill]);
+    assert_eq!(len, 2048);
+    assert_eq!(largest, 2);
+    assert_eq!(&sent[..], &expected[..]);
+
+    // strings go to the sink whole
+    let options = Config::new().with_length::<U16, 100>();
+    let mut writes = Vec::new();
+    let sink = |bytes: &[u8]| -> Result<(), ()> {
+        writes.push(bytes.to_vec());
+        Ok(())
+    };
+    assert_eq!(to_sink_with(options, sink, &(7u8, "abc")).unwrap(), 6);
+    assert_eq!(writes, vec![vec![7], vec![0, 3], b"abc".to_vec()]);
+
+    #[derive(Debug, PartialEq)]
+    struct Full;
+    let mut room = 5;
+    let sink = |bytes: &[u8]| {
+        if bytes.len() > room {
+            return Err(Full);
+        }
+        room -= bytes.len();
+        Ok(())
+    };
+    assert_eq!(to_sink(sink, &(1u32, 2u32)).unwrap_err(), SinkError::Sink(Full));
+    assert_eq!(to_sink(|_: &[u8]| Err(Full), "abc").unwrap_err(),
+               SinkError::Ser(SerError::Unsupported(Unsupported::Str)));
+}
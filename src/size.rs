@@ -0,0 +1,178 @@
+//! pointer sized integer module
+//!
+//! serde hands `usize` and `isize` to the serializer as `u64`/`i64`, so they
+//! always take up 8 bytes. Wrapped in `Usize`/`Isize` they are as wide as the
+//! options say instead (`Config::with_usize`), i.e. to match a 16 bit peer:
+//!
+//! ```
+//! use ubyte::{Isize, Usize};
+//! use ubyte::options::{Config, U16};
+//!
+//! let options = Config::new().with_usize::<U16>();
+//!
+//! let mut buffer = [0u8; 8];
+//! let len = ubyte::to_bytes_with(options, &mut buffer, &(Usize(300), Isize(-2))).unwrap();
+//! assert_eq!(&buffer[..len], &[0x01, 0x2C, 0xFF, 0xFE]);
+//! ```
+
+use core::convert::TryFrom;
+use core::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, Unexpected, Visitor};
+use serde::{Serialize, Serializer};
+
+use crate::max_size::{int_size, MaxSizeWith};
+use crate::options::{Options, UsizeWidth};
+
+/// The newtype names `Usize` and `Isize` pass to the serializer and
+/// deserializer, which then use the width from the options.
+#[doc(hidden)]
+pub const USIZE_NAME: &str = "ubyte::Usize";
+#[doc(hidden)]
+pub const ISIZE_NAME: &str = "ubyte::Isize";
+
+/// A `usize` that is `Options::Usize` wide.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Usize(pub usize);
+
+/// An `isize` that is `Options::Usize` wide.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Isize(pub isize);
+
+impl From<usize> for Usize {
+    fn from(v: usize) -> Usize {
+        Usize(v)
+    }
+}
+
+impl From<isize> for Isize {
+    fn from(v: isize) -> Isize {
+        Isize(v)
+    }
+}
+
+impl Serialize for Usize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(USIZE_NAME, &(self.0 as u64))
+    }
+}
+
+impl Serialize for Isize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(ISIZE_NAME, &(self.0 as i64))
+    }
+}
+
+struct UsizeVisitor;
+
+impl<'de> Visitor<'de> for UsizeVisitor {
+    type Value = Usize;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a usize")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D)
+        -> Result<Usize, D::Error>
+    {
+        let v = u64::deserialize(deserializer)?;
+        match usize::try_from(v) {
+            Ok(v) => Ok(Usize(v)),
+            Err(_) => Err(de::Error::invalid_value(Unexpected::Unsigned(v), &self)),
+        }
+    }
+}
+
+struct IsizeVisitor;
+
+impl<'de> Visitor<'de> for IsizeVisitor {
+    type Value = Isize;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an isize")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D)
+        -> Result<Isize, D::Error>
+    {
+        let v = i64::deserialize(deserializer)?;
+        match isize::try_from(v) {
+            Ok(v) => Ok(Isize(v)),
+            Err(_) => Err(de::Error::invalid_value(Unexpected::Signed(v), &self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Usize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(USIZE_NAME, UsizeVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Isize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(ISIZE_NAME, IsizeVisitor)
+    }
+}
+
+impl<O: Options> MaxSizeWith<O> for Usize {
+    const MAX_SIZE: usize = int_size::<O>(O::Usize::MAX_SIZE);
+}
+
+impl<O: Options> MaxSizeWith<O> for Isize {
+    const MAX_SIZE: usize = int_size::<O>(O::Usize::MAX_SIZE);
+}
+
+#[test]
+fn test_size() {
+    use crate::de::{from_bytes, from_bytes_with};
+    use crate::error::{DeError, SerError};
+    use crate::max_size::MaxSize;
+    use crate::options::{BigEndian, Config, Disabled, FixintEncoding, LittleEndian, U16, U32, U8,
+                         VarintEncoding};
+    use crate::ser::{to_bytes, to_bytes_with};
+
+    let mut buffer = [0u8; 16];
+
+    // 8 bytes by default, like plain `usize`/`isize`
+    let value = (Usize(1), Isize(-1), 2usize);
+    assert_eq!(<(Usize, Isize, usize) as MaxSize>::MAX_SIZE, 24);
+    let mut wide = [0u8; 24];
+    to_bytes(&mut wide, &value).unwrap();
+    assert_eq!(wide[..8], [0, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(wide[8..16], [0xFF; 8]);
+    assert_eq!(from_bytes::<(Usize, Isize, usize)>(&wide).unwrap(), value);
+
+    // only the wrapped values are narrowed
+    let options = Config::new().with_little_endian().with_usize::<U16>();
+    let value = (Usize(0x0102), Isize(-2), 3u64);
+    type Narrow = Config<LittleEndian, U8, Disabled, 0, FixintEncoding, U16>;
+    assert_eq!(<(Usize, Isize, u64) as MaxSizeWith<Narrow>>::MAX_SIZE, 12);
+    let len = to_bytes_with(options, &mut buffer, &value).unwrap();
+    assert_eq!(&buffer[..len], &[2, 1, 0xFE, 0xFF, 3, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(from_bytes_with::<_, (Usize, Isize, u64)>(options, &buffer[..len]).unwrap(), value);
+
+    assert_eq!(to_bytes_with(options, &mut buffer, &Usize(0x10000)).unwrap_err(),
+               SerError::IntLarge);
+    assert_eq!(to_bytes_with(options, &mut buffer, &Isize(-0x8001)).unwrap_err(),
+               SerError::IntLarge);
+    assert_eq!(from_bytes_with::<_, Usize>(options, &[1]).unwrap_err(), DeError::BufferSmall);
+
+    let options = Config::new().with_usize::<U32>();
+    let len = to_bytes_with(options, &mut buffer, &Isize(-0x10000)).unwrap();
+    assert_eq!(&buffer[..len], &[0xFF, 0xFF, 0, 0]);
+    assert_eq!(from_bytes_with::<_, Isize>(options, &buffer[..len]).unwrap(), Isize(-0x10000));
+
+    // varints are checked against the width too
+    let options = options.with_varint_encoding();
+    type NarrowVarints = Config<BigEndian, U8, Disabled, 0, VarintEncoding, U32>;
+    assert_eq!(<(Usize, Isize) as MaxSizeWith<NarrowVarints>>::MAX_SIZE, 5 + 5);
+    let len = to_bytes_with(options, &mut buffer, &(Usize(300), Isize(-1))).unwrap();
+    assert_eq!(&buffer[..len], &[0xAC, 0x02, 1]);
+    assert_eq!(from_bytes_with::<_, (Usize, Isize)>(options, &buffer[..len]).unwrap(),
+               (Usize(300), Isize(-1)));
+    assert_eq!(to_bytes_with(options, &mut buffer, &Usize(0x1_0000_0000)).unwrap_err(),
+               SerError::IntLarge);
+    assert_eq!(from_bytes_with::<_, Usize>(options, &[0x80, 0x80, 0x80, 0x80, 0x10]).unwrap_err(),
+               DeError::InvalidVarint);
+}
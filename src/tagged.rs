@@ -0,0 +1,456 @@
+//! explicit enum tag module
+//!
+//! By default an enum variant is encoded as its position in the enum, so
+//! reordering or removing variants changes the encoding. `EnumTags` gives every
+//! variant a fixed tag instead, which is used when the enum is wrapped in
+//! `Tagged` (or a field uses `#[serde(with = "ubyte::tagged")]`).
+//! `#[derive(EnumTags)]` (requires the `derive` feature) takes the tag from a
+//! `#[ubyte(tag = ..)]` attribute or else the explicit discriminant:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate ubyte;
+//! use ubyte::{EnumTags, Tagged};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! enum Command {
+//!     Move { x: i16, y: i16 },
+//!     Stop,
+//! }
+//!
+//! // `Stop` was the first variant in the original protocol
+//! impl EnumTags for Command {
+//!     const TAGS: &'static [u32] = &[1, 0];
+//! }
+//!
+//! # fn main() {
+//! let mut buffer = [0u8; 8];
+//! let len = ubyte::to_bytes(&mut buffer, &Tagged(Command::Stop)).unwrap();
+//! assert_eq!(&buffer[..len], &[0]);
+//! let command: Tagged<Command> = ubyte::from_bytes(&buffer[..len]).unwrap();
+//! assert_eq!(command.0, Command::Stop);
+//! # }
+//! ```
+//!
+//! The tags are written with the configured tag width, see
+//! `Config::with_tag`. Duplicate tags fail to compile wherever the enum is
+//! serialized or deserialized, and already at the enum with the derive:
+//!
+//! ```compile_fail
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate ubyte;
+//! use ubyte::{EnumTags, Tagged};
+//!
+//! #[derive(Deserialize)]
+//! enum Command {
+//!     Move { x: i16, y: i16 },
+//!     Stop,
+//! }
+//!
+//! impl EnumTags for Command {
+//!     const TAGS: &'static [u32] = &[1, 1];
+//! }
+//!
+//! # fn main() {
+//! let command: Tagged<Command> = ubyte::from_bytes(&[1, 0, 0, 0, 0]).unwrap();
+//! # }
+//! ```
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, Expected,
+                IntoDeserializer, VariantAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use serde::ser::{self, Impossible, Serialize, Serializer};
+
+use crate::max_size::MaxSizeWith;
+use crate::options::Options;
+
+/// An enum with a fixed tag for every variant.
+pub trait EnumTags {
+    /// The tag of every variant, in declaration order. Tags must be unique.
+    const TAGS: &'static [u32];
+}
+
+/// The enum name `Tagged` passes to the deserializer, which turns off its
+/// check of the tag against the number of variants.
+#[doc(hidden)]
+pub const NAME: &str = "ubyte::Tagged";
+
+/// What `Tagged` expects when it rejects a tag missing from `EnumTags::TAGS`.
+/// The deserializer recognizes it by its address and reports
+/// `DeError::InvalidVariant` rather than `DeError::InvalidValue`.
+pub(crate) struct Listed {
+    _byte: u8,
+}
+
+// not zero sized, so it has an address of its own
+pub(crate) static LISTED: Listed = Listed { _byte: 0 };
+
+impl Expected for Listed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a tag in EnumTags::TAGS")
+    }
+}
+
+/// Whether `exp` is `LISTED`.
+pub(crate) fn is_listed(exp: &dyn Expected) -> bool {
+    ptr::eq(exp as *const dyn Expected as *const u8, &LISTED as *const Listed as *const u8)
+}
+
+/// An enum encoded with the tags from `EnumTags` instead of the variant
+/// positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tagged<E>(pub E);
+
+impl<E: EnumTags> Tagged<E> {
+    const ASSERT_UNIQUE: () = check_tags(E::TAGS);
+}
+
+/// Fail if `tags` contains duplicates, at compile time when used in a const.
+#[doc(hidden)]
+pub const fn check_tags(tags: &[u32]) {
+    assert!(unique(tags), "EnumTags::TAGS contains duplicates");
+}
+
+const fn unique(tags: &[u32]) -> bool {
+    let mut i = 0;
+    while i < tags.len() {
+        let mut j = i + 1;
+        while j < tags.len() {
+            if tags[i] == tags[j] {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Check a tag written by `#[derive(EnumTags)]` at compile time.
+#[doc(hidden)]
+pub const fn tag(tag: i128) -> u32 {
+    assert!(0 <= tag && tag <= u32::MAX as i128, "EnumTags tags must fit in a u32");
+    tag as u32
+}
+
+impl<E> From<E> for Tagged<E> {
+    fn from(v: E) -> Tagged<E> {
+        Tagged(v)
+    }
+}
+
+impl<E: Serialize + EnumTags> Serialize for Tagged<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, E: Deserialize<'de> + EnumTags> Deserialize<'de> for Tagged<E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer).map(Tagged)
+    }
+}
+
+impl<O: Options, E: MaxSizeWith<O>> MaxSizeWith<O> for Tagged<E> {
+    const MAX_SIZE: usize = E::MAX_SIZE;
+}
+
+/// Serialize `value` with its `EnumTags`, for `#[serde(with = "ubyte::tagged")]`.
+pub fn serialize<E, S>(value: &E, serializer: S) -> Result<S::Ok, S::Error>
+    where E: Serialize + EnumTags,
+          S: Serializer
+{
+    #[allow(clippy::let_unit_value)]
+    let () = Tagged::<E>::ASSERT_UNIQUE;
+    value.serialize(TagSerializer { inner: serializer, tags: E::TAGS })
+}
+
+/// Deserialize an enum encoded with its `EnumTags`, for
+/// `#[serde(with = "ubyte::tagged")]`.
+pub fn deserialize<'de, E, D>(deserializer: D) -> Result<E, D::Error>
+    where E: Deserialize<'de> + EnumTags,
+          D: Deserializer<'de>
+{
+    #[allow(clippy::let_unit_value)]
+    let () = Tagged::<E>::ASSERT_UNIQUE;
+    deserializer.deserialize_enum(NAME, &[], TaggedVisitor { value: PhantomData })
+}
+
+// Serialization: replace the variant index by its tag and forward to the
+// wrapped serializer.
+
+struct TagSerializer<S> {
+    inner: S,
+    tags: &'static [u32],
+}
+
+impl<S: Serializer> TagSerializer<S> {
+    fn tag(&self, variant_index: u32) -> Result<u32, S::Error> {
+        match self.tags.get(variant_index as usize) {
+            Some(&tag) => Ok(tag),
+            None => Err(ser::Error::custom("variant has no tag in EnumTags::TAGS")),
+        }
+    }
+}
+
+fn not_enum<E: ser::Error>() -> E {
+    ser::Error::custom("Tagged can only wrap enums")
+}
+
+macro_rules! not_enum {
+    ($($method:ident($($ty:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<$ok, S::Error> {
+                Err(not_enum())
+            }
+        )*
+    }
+}
+
+impl<S: Serializer> Serializer for TagSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    type SerializeSeq = Impossible<S::Ok, S::Error>;
+    type SerializeTuple = Impossible<S::Ok, S::Error>;
+    type SerializeTupleStruct = Impossible<S::Ok, S::Error>;
+    type SerializeTupleVariant = S::SerializeTupleVariant;
+    type SerializeMap = Impossible<S::Ok, S::Error>;
+    type SerializeStruct = Impossible<S::Ok, S::Error>;
+    type SerializeStructVariant = S::SerializeStructVariant;
+
+    not_enum! {
+        serialize_bool(bool) -> S::Ok;
+        serialize_i8(i8) -> S::Ok;
+        serialize_i16(i16) -> S::Ok;
+        serialize_i32(i32) -> S::Ok;
+        serialize_i64(i64) -> S::Ok;
+        serialize_u8(u8) -> S::Ok;
+        serialize_u16(u16) -> S::Ok;
+        serialize_u32(u32) -> S::Ok;
+        serialize_u64(u64) -> S::Ok;
+        serialize_f32(f32) -> S::Ok;
+        serialize_f64(f64) -> S::Ok;
+        serialize_char(char) -> S::Ok;
+        serialize_str(&str) -> S::Ok;
+        serialize_bytes(&[u8]) -> S::Ok;
+        serialize_none() -> S::Ok;
+        serialize_unit() -> S::Ok;
+        serialize_unit_struct(&'static str) -> S::Ok;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<S::Ok, S::Error> {
+        Err(not_enum())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<S::Ok, S::Error>
+        where T: ?Sized + Serialize
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(self,
+                              name: &'static str,
+                              variant_index: u32,
+                              variant: &'static str)
+                              -> Result<S::Ok, S::Error> {
+        let tag = self.tag(variant_index)?;
+        self.inner.serialize_unit_variant(name, tag, variant)
+    }
+
+    fn serialize_newtype_variant<T>(self,
+                                    name: &'static str,
+                                    variant_index: u32,
+                                    variant: &'static str,
+                                    value: &T)
+                                    -> Result<S::Ok, S::Error>
+        where T: ?Sized + Serialize
+    {
+        let tag = self.tag(variant_index)?;
+        self.inner.serialize_newtype_variant(name, tag, variant, value)
+    }
+
+    fn serialize_tuple_variant(self,
+                               name: &'static str,
+                               variant_index: u32,
+                               variant: &'static str,
+                               len: usize)
+                               -> Result<Self::SerializeTupleVariant, S::Error> {
+        let tag = self.tag(variant_index)?;
+        self.inner.serialize_tuple_variant(name, tag, variant, len)
+    }
+
+    fn serialize_struct_variant(self,
+                                name: &'static str,
+                                variant_index: u32,
+                                variant: &'static str,
+                                len: usize)
+                                -> Result<Self::SerializeStructVariant, S::Error> {
+        let tag = self.tag(variant_index)?;
+        self.inner.serialize_struct_variant(name, tag, variant, len)
+    }
+}
+
+// Deserialization: read the tag, map it back to the variant index and
+// deserialize the enum from that index and the rest of the input.
+
+struct TaggedVisitor<E> {
+    value: PhantomData<E>,
+}
+
+impl<'de, E: Deserialize<'de> + EnumTags> Visitor<'de> for TaggedVisitor<E> {
+    type Value = E;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a tagged enum")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<E, A::Error> {
+        let (index, variant) = data.variant_seed(TagSeed { tags: E::TAGS })?;
+        E::deserialize(IndexedEnum { index, variant })
+    }
+}
+
+/// Reads a tag, producing the variant index.
+struct TagSeed {
+    tags: &'static [u32],
+}
+
+impl<'de> DeserializeSeed<'de> for TagSeed {
+    type Value = u32;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<u32, D::Error> {
+        let tag = u32::deserialize(deserializer)?;
+        match self.tags.iter().position(|&t| t == tag) {
+            Some(index) => Ok(index as u32),
+            None => Err(de::Error::invalid_value(de::Unexpected::Unsigned(tag.into()), &LISTED)),
+        }
+    }
+}
+
+/// A deserializer for an enum whose variant index is already known.
+struct IndexedEnum<A> {
+    index: u32,
+    variant: A,
+}
+
+impl<'de, A: VariantAccess<'de>> Deserializer<'de> for IndexedEnum<A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, A::Error> {
+        Err(de::Error::custom("Tagged can only wrap enums"))
+    }
+
+    fn deserialize_enum<V>(self,
+                           _name: &'static str,
+                           _variants: &'static [&'static str],
+                           visitor: V)
+                           -> Result<V::Value, A::Error>
+        where V: Visitor<'de>
+    {
+        visitor.visit_enum(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> EnumAccess<'de> for IndexedEnum<A> {
+    type Error = A::Error;
+    type Variant = A;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, A), A::Error>
+        where V: DeserializeSeed<'de>
+    {
+        let value = seed.deserialize(self.index.into_deserializer())?;
+        Ok((value, self.variant))
+    }
+}
+
+#[test]
+fn test_tagged() {
+    use crate::de::{from_bytes, from_bytes_context, from_bytes_with};
+    use crate::error::{DeError, SerError};
+    use crate::options::{Config, U16};
+    use crate::ser::{to_bytes, to_bytes_with};
+
+    // version 2 of the protocol: `Ping` was removed, `Move` grew a field
+    // and the variants were reordered
+    #[derive(Debug, PartialEq, Serialize, Deserialize, MaxSize, EnumTags)]
+    enum Command {
+        #[ubyte(tag = 7)]
+        Move { x: i16, y: i16, speed: u8 },
+        #[ubyte(tag = 0x42)]
+        Stop,
+        #[ubyte(tag = 3)]
+        Rotate(i16),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Frame {
+        seq: u8,
+        #[serde(with = "crate::tagged")]
+        command: Command,
+    }
+
+    assert_eq!(Command::TAGS, &[7, 0x42, 3]);
+    assert_eq!(<Tagged<Command> as crate::MaxSize>::MAX_SIZE,
+               <Command as crate::MaxSize>::MAX_SIZE);
+
+    let mut buffer = [0u8; 16];
+    let len = to_bytes(&mut buffer, &Tagged(Command::Stop)).unwrap();
+    assert_eq!(&buffer[..len], &[0x42]);
+    assert_eq!(from_bytes::<Tagged<Command>>(&buffer[..len]).unwrap(), Tagged(Command::Stop));
+
+    let frame = Frame { seq: 9, command: Command::Move { x: 1, y: -1, speed: 3 } };
+    let len = to_bytes(&mut buffer, &frame).unwrap();
+    assert_eq!(&buffer[..len], &[9, 7, 0, 1, 0xFF, 0xFF, 3]);
+    assert_eq!(from_bytes::<Frame>(&buffer[..len]).unwrap(), frame);
+
+    let options = Config::new().with_tag::<U16>();
+    let len = to_bytes_with(options, &mut buffer, &Tagged(Command::Rotate(-2))).unwrap();
+    assert_eq!(&buffer[..len], &[0, 3, 0xFF, 0xFE]);
+    assert_eq!(from_bytes_with::<_, Tagged<Command>>(options, &buffer[..len]).unwrap(),
+               Tagged(Command::Rotate(-2)));
+
+    // unknown tags, including the index of a variant
+    assert_eq!(from_bytes::<Tagged<Command>>(&[1]).unwrap_err(), DeError::InvalidVariant);
+    let context = from_bytes_context::<Frame>(&[9, 0]).unwrap_err();
+    assert_eq!(context.error, DeError::InvalidVariant);
+    assert_eq!(context.offset, 1);
+    #[cfg(feature = "path")]
+    assert_eq!(context.path.to_string(), "Frame.command");
+
+    // discriminants are used without the attribute
+    #[derive(Debug, PartialEq, Serialize, Deserialize, EnumTags)]
+    #[repr(u8)]
+    enum Mode {
+        Off = 0x10,
+        On = 0x20,
+    }
+
+    assert_eq!(Mode::TAGS, &[0x10, 0x20]);
+    let len = to_bytes(&mut buffer, &Tagged(Mode::On)).unwrap();
+    assert_eq!(&buffer[..len], &[0x20]);
+    assert_eq!(from_bytes::<Tagged<Mode>>(&[0x10]).unwrap(), Tagged(Mode::Off));
+
+    // tags are still limited by the tag width
+    #[derive(Serialize, EnumTags)]
+    enum Wide {
+        #[ubyte(tag = 300)]
+        A,
+    }
+    assert_eq!(to_bytes(&mut buffer, &Tagged(Wide::A)).unwrap_err(), SerError::EnumLarge);
+}
@@ -0,0 +1,283 @@
+//! variable length integer module
+//!
+//! Unsigned integers are encoded as LEB128: seven bits per byte, least
+//! significant group first, with the high bit set on every byte but the last.
+//! Signed integers are zigzag encoded first (`0, -1, 1, -2, ...` become
+//! `0, 1, 2, 3, ...`) so small negative numbers stay small.
+//!
+//! The whole serializer can use this encoding through
+//! `Config::with_varint_encoding`, or single fields can opt in by being
+//! wrapped in `Varint`:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate ubyte;
+//! use ubyte::Varint;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Counters {
+//!     sent: Varint<u64>,
+//!     rssi: Varint<i16>,
+//! }
+//!
+//! # fn main() {
+//! let mut buffer = [0u8; 16];
+//! let counters = Counters { sent: Varint(300), rssi: Varint(-3) };
+//! let len = ubyte::to_bytes(&mut buffer, &counters).unwrap();
+//! assert_eq!(&buffer[..len], &[0xAC, 0x02, 0x05]);
+//! # }
+//! ```
+
+use core::convert::TryFrom;
+use core::fmt;
+use core::ptr;
+
+use serde::de::{self, Deserialize, Deserializer, Expected, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+use crate::max_size::MaxSizeWith;
+use crate::options::Options;
+
+/// The largest number of bytes a varint can take up (a `u64`).
+pub(crate) const MAX_LEN: usize = 10;
+
+/// The newtype name `Varint` passes to the serializer and deserializer. The
+/// deserializer then decodes the varint itself rather than byte by byte.
+#[doc(hidden)]
+pub const NAME: &str = "ubyte::Varint";
+
+/// What `Varint` expects when it rejects an overlong or out of range varint.
+/// The deserializer recognizes it by its address and reports
+/// `DeError::InvalidVarint` rather than `DeError::InvalidValue`.
+pub(crate) struct Fitting {
+    _byte: u8,
+}
+
+// not zero sized, so it has an address of its own
+pub(crate) static FITTING: Fitting = Fitting { _byte: 0 };
+
+impl Expected for Fitting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a varint that fits the type")
+    }
+}
+
+/// Whether `exp` is `FITTING`.
+pub(crate) fn is_fitting(exp: &dyn Expected) -> bool {
+    ptr::eq(exp as *const dyn Expected as *const u8, &FITTING as *const Fitting as *const u8)
+}
+
+/// The largest number of bytes a varint encoding of an integer that is
+/// `bytes` wide can take up.
+pub(crate) const fn max_len(bytes: usize) -> usize {
+    (bytes * 8).div_ceil(7)
+}
+
+/// Encode `v` into `buffer`, returning the number of bytes used.
+#[inline(always)]
+pub(crate) fn encode(mut v: u64, buffer: &mut [u8; MAX_LEN]) -> usize {
+    let mut len = 0;
+    while v >= 0x80 {
+        buffer[len] = v as u8 | 0x80;
+        v >>= 7;
+        len += 1;
+    }
+    buffer[len] = v as u8;
+    len + 1
+}
+
+/// Accumulates the bytes of a varint.
+pub(crate) struct Decoder {
+    value: u64,
+    shift: u32,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Decoder {
+        Decoder { value: 0, shift: 0 }
+    }
+
+    /// Add the next byte. Returns the value once the last byte was pushed or
+    /// `Err(())` if the encoding doesn't fit in a `u64`.
+    #[inline(always)]
+    #[allow(clippy::result_unit_err)]
+    pub(crate) fn push(&mut self, byte: u8) -> Result<Option<u64>, ()> {
+        let bits = (byte & 0x7F) as u64;
+        if self.shift == 63 && bits > 1 {
+            return Err(());
+        }
+        self.value |= bits << self.shift;
+        if byte & 0x80 == 0 {
+            return Ok(Some(self.value));
+        }
+        self.shift += 7;
+        if self.shift > 63 {
+            return Err(());
+        }
+        Ok(None)
+    }
+}
+
+#[inline(always)]
+pub(crate) fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+#[inline(always)]
+pub(crate) fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+/// An integer that is always varint encoded, whatever the options.
+///
+/// Encoded as a newtype holding a tuple of bytes, so it works with any serde
+/// serializer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Varint<T>(pub T);
+
+impl<T> From<T> for Varint<T> {
+    fn from(v: T) -> Varint<T> {
+        Varint(v)
+    }
+}
+
+/// The bytes of an encoded varint.
+struct Encoded {
+    buffer: [u8; MAX_LEN],
+    len: usize,
+}
+
+impl Serialize for Encoded {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(self.len)?;
+        for byte in &self.buffer[..self.len] {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+}
+
+fn serialize_varint<S: Serializer>(v: u64, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut encoded = Encoded { buffer: [0u8; MAX_LEN], len: 0 };
+    encoded.len = encode(v, &mut encoded.buffer);
+    serializer.serialize_newtype_struct(NAME, &encoded)
+}
+
+struct VarintVisitor;
+
+impl<'de> Visitor<'de> for VarintVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a varint")
+    }
+
+    // ubyte's deserializer hands over the decoded value
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        Ok(v)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D)
+        -> Result<u64, D::Error>
+    {
+        deserializer.deserialize_tuple(MAX_LEN, self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<u64, A::Error> {
+        let mut decoder = Decoder::new();
+        for len in 0..MAX_LEN {
+            let byte: u8 = match seq.next_element()? {
+                Some(byte) => byte,
+                None => return Err(de::Error::invalid_length(len, &self)),
+            };
+            match decoder.push(byte) {
+                Ok(Some(v)) => return Ok(v),
+                Ok(None) => {}
+                Err(()) => break,
+            }
+        }
+        Err(de::Error::invalid_value(de::Unexpected::Other("overlong varint"), &FITTING))
+    }
+}
+
+fn deserialize_varint<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserializer.deserialize_newtype_struct(NAME, VarintVisitor)
+}
+
+macro_rules! impl_varint {
+    ($($ty:ty => $to:expr, $from:expr;)*) => {
+        $(
+            impl Serialize for Varint<$ty> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let to: fn($ty) -> u64 = $to;
+                    serialize_varint(to(self.0), serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for Varint<$ty> {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let from: fn(u64) -> Option<$ty> = $from;
+                    let v = deserialize_varint(deserializer)?;
+                    match from(v) {
+                        Some(v) => Ok(Varint(v)),
+                        None => Err(de::Error::invalid_value(de::Unexpected::Unsigned(v),
+                                                             &FITTING)),
+                    }
+                }
+            }
+
+            impl<O: Options> MaxSizeWith<O> for Varint<$ty> {
+                const MAX_SIZE: usize = max_len(::core::mem::size_of::<$ty>());
+            }
+        )*
+    }
+}
+
+impl_varint! {
+    u16 => |v| v as u64, |v| u16::try_from(v).ok();
+    u32 => |v| v as u64, |v| u32::try_from(v).ok();
+    u64 => |v| v, Some;
+    usize => |v| v as u64, |v| usize::try_from(v).ok();
+    i16 => |v| zigzag(v as i64), |v| i16::try_from(unzigzag(v)).ok();
+    i32 => |v| zigzag(v as i64), |v| i32::try_from(unzigzag(v)).ok();
+    i64 => zigzag, |v| Some(unzigzag(v));
+    isize => |v| zigzag(v as i64), |v| isize::try_from(unzigzag(v)).ok();
+}
+
+#[test]
+fn test_varint() {
+    use crate::de::from_bytes;
+    use crate::error::DeError;
+    use crate::ser::to_bytes;
+
+    let mut buffer = [0u8; MAX_LEN];
+    let mut roundtrip = |v: u64, bytes: &[u8]| {
+        assert_eq!(encode(v, &mut buffer), bytes.len());
+        assert_eq!(&buffer[..bytes.len()], bytes);
+        assert_eq!(from_bytes::<Varint<u64>>(bytes).unwrap(), Varint(v));
+    };
+    roundtrip(0, &[0]);
+    roundtrip(0x7F, &[0x7F]);
+    roundtrip(0x80, &[0x80, 0x01]);
+    roundtrip(300, &[0xAC, 0x02]);
+    roundtrip(u64::MAX, &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+
+    for &(v, z) in &[(0i64, 0u64), (-1, 1), (1, 2), (-2, 3), (i64::MAX, u64::MAX - 1),
+                     (i64::MIN, u64::MAX)] {
+        assert_eq!(zigzag(v), z);
+        assert_eq!(unzigzag(z), v);
+    }
+
+    let mut buffer = [0u8; 16];
+    let len = to_bytes(&mut buffer, &(Varint(-64i32), Varint(u16::MAX))).unwrap();
+    assert_eq!(&buffer[..len], &[0x7F, 0xFF, 0xFF, 0x03]);
+
+    // too large for the type, or for a u64, same as with `VarintEncoding`
+    assert_eq!(from_bytes::<Varint<u16>>(&[0xFF, 0xFF, 0x04]).unwrap_err(), DeError::InvalidVarint);
+    assert_eq!(from_bytes::<Varint<u64>>(&[0xFF; 10]).unwrap_err(), DeError::InvalidVarint);
+    assert_eq!(from_bytes::<Varint<u64>>(&[0xFF, 0xFF]).unwrap_err(), DeError::BufferSmall);
+
+    assert_eq!(<Varint<u16> as crate::MaxSize>::MAX_SIZE, 3);
+    assert_eq!(<Varint<i32> as crate::MaxSize>::MAX_SIZE, 5);
+    assert_eq!(<Varint<u64> as crate::MaxSize>::MAX_SIZE, 10);
+}
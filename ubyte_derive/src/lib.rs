@@ -0,0 +1,171 @@
+//! Derive macros for the ubyte serialization library.
+//!
+//! Use these through the `derive` feature of `ubyte` rather than depending on
+//! this crate directly.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Expr, Fields, GenericParam, Variant};
+
+/// Derive `ubyte::MaxSizeWith<O>` for all options `O`, and through it
+/// `ubyte::MaxSize`.
+///
+/// Structs are the sum of their fields. Enums are the variant tag plus the
+/// largest variant. Every type parameter is required to implement
+/// `MaxSizeWith<O>`.
+#[proc_macro_derive(MaxSize)]
+pub fn derive_max_size(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+
+    for param in &mut input.generics.params {
+        if let GenericParam::Type(ref mut ty) = *param {
+            ty.bounds.push(parse_quote!(::ubyte::MaxSizeWith<__O>));
+        }
+    }
+    // the options are the impl's last generic parameter
+    let mut impl_generics = input.generics.clone();
+    impl_generics.params.push(parse_quote!(__O: ::ubyte::Options));
+
+    let size = match input.data {
+        Data::Struct(ref data) => fields_size(&data.fields),
+        Data::Enum(ref data) => {
+            if data.variants.is_empty() {
+                quote!(0)
+            } else {
+                let largest = data.variants.iter().fold(quote!(0), |acc, variant| {
+                    let size = fields_size(&variant.fields);
+                    quote!(::ubyte::max_size::max(#acc, #size))
+                });
+                quote!(::ubyte::max_size::tag_size::<__O>() + #largest)
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new_spanned(&input.ident, "MaxSize cannot be derived for unions")
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::ubyte::MaxSizeWith<__O> for #name #ty_generics #where_clause {
+            const MAX_SIZE: usize = #size;
+        }
+    };
+    expanded.into()
+}
+
+/// Derive `ubyte::EnumTags`.
+///
+/// The tag of each variant is taken from its `#[ubyte(tag = ..)]` attribute,
+/// or else its explicit discriminant.
+#[proc_macro_derive(EnumTags, attributes(ubyte))]
+pub fn derive_enum_tags(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => {
+            return syn::Error::new_spanned(&input.ident, "EnumTags can only be derived for enums")
+                .to_compile_error()
+                .into();
+        }
+    };
+    let mut tags = Vec::new();
+    let mut literals = Vec::new();
+    for variant in &data.variants {
+        let tag = match variant_tag(variant) {
+            Ok(tag) => tag,
+            Err(err) => return err.to_compile_error().into(),
+        };
+        // literal tags are checked here, everything else by `ubyte::tagged::tag`
+        match literal_tag(&tag) {
+            Some(value) if value < 0 || value > i128::from(u32::MAX) => {
+                return syn::Error::new_spanned(&tag, "EnumTags tags must fit in a u32")
+                    .to_compile_error()
+                    .into();
+            }
+            Some(value) if literals.contains(&value) => {
+                return syn::Error::new_spanned(&tag, "duplicate EnumTags tag")
+                    .to_compile_error()
+                    .into();
+            }
+            Some(value) => literals.push(value),
+            None => (),
+        }
+        tags.push(tag);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut expanded = quote! {
+        impl #impl_generics ::ubyte::EnumTags for #name #ty_generics #where_clause {
+            const TAGS: &'static [u32] = &[#(::ubyte::tagged::tag((#tags) as i128)),*];
+        }
+    };
+    if input.generics.params.is_empty() {
+        // evaluate the tags and their uniqueness check where the enum is defined
+        expanded.extend(quote! {
+            const _: () = ::ubyte::tagged::check_tags(<#name as ::ubyte::EnumTags>::TAGS);
+        });
+    }
+    expanded.into()
+}
+
+/// The value of an integer literal tag, possibly negated.
+fn literal_tag(tag: &Expr) -> Option<i128> {
+    match tag {
+        Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(int), .. }) => int.base10_parse().ok(),
+        Expr::Unary(syn::ExprUnary { op: syn::UnOp::Neg(_), expr, .. }) => {
+            literal_tag(expr).map(|value| -value)
+        }
+        Expr::Paren(syn::ExprParen { expr, .. }) | Expr::Group(syn::ExprGroup { expr, .. }) => {
+            literal_tag(expr)
+        }
+        _ => None,
+    }
+}
+
+/// The `#[ubyte(tag = ..)]` attribute or the discriminant of a variant.
+fn variant_tag(variant: &Variant) -> syn::Result<Expr> {
+    let mut tag = None;
+    for attr in &variant.attrs {
+        if !attr.path().is_ident("ubyte") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                tag = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `tag = ..`"))
+            }
+        })?;
+    }
+    if let Some(tag) = tag {
+        return Ok(tag);
+    }
+    match variant.discriminant {
+        Some((_, ref discriminant)) => Ok(discriminant.clone()),
+        None => Err(syn::Error::new_spanned(&variant.ident,
+                                            "variant needs a `#[ubyte(tag = ..)]` attribute \
+                                             or an explicit discriminant")),
+    }
+}
+
+/// Sum of the maximum sizes of every field.
+fn fields_size(fields: &Fields) -> TokenStream2 {
+    let sizes = fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote!(<#ty as ::ubyte::MaxSizeWith<__O>>::MAX_SIZE)
+    });
+    quote!(0 #(+ #sizes)*)
+}